@@ -0,0 +1,5 @@
+ALTER TABLE envios ADD COLUMN token TEXT;
+ALTER TABLE envios ADD COLUMN confirmado_em TEXT;
+ALTER TABLE outbox ADD COLUMN token TEXT;
+
+CREATE UNIQUE INDEX envios_token ON envios (token);
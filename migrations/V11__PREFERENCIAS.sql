@@ -0,0 +1,9 @@
+ALTER TABLE jogadores ADD COLUMN grupo TEXT;
+ALTER TABLE jogadores ADD COLUMN idioma TEXT;
+ALTER TABLE jogadores ADD COLUMN lista_desejos TEXT;
+
+CREATE TABLE exclusoes (
+    jogador INTEGER NOT NULL REFERENCES jogadores (id) ON DELETE CASCADE,
+    excluido INTEGER NOT NULL REFERENCES jogadores (id) ON DELETE CASCADE,
+    PRIMARY KEY (jogador, excluido)
+);
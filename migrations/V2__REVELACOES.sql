@@ -0,0 +1,7 @@
+CREATE TABLE revelacoes (
+    id INTEGER PRIMARY KEY NOT NULL,
+    envio INTEGER REFERENCES envios (id) ON DELETE SET NULL,
+    sorteio INTEGER REFERENCES sorteios (id) ON DELETE CASCADE,
+    comando TEXT NOT NULL,
+    momento TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,19 @@
+ALTER TABLE sorteios ADD COLUMN sal TEXT;
+ALTER TABLE sorteios ADD COLUMN verificador BLOB;
+
+CREATE TABLE envios_novo (
+    id INTEGER PRIMARY KEY NOT NULL,
+    sorteio INTEGER REFERENCES sorteios (id) NOT NULL,
+    destino INTEGER REFERENCES jogadores (id) NOT NULL,
+    sorteado INTEGER REFERENCES jogadores (id),
+    sorteado_cifrado BLOB,
+    sucesso BOOLEAN NOT NULL,
+    erro TEXT
+);
+
+INSERT INTO envios_novo (id, sorteio, destino, sorteado, sucesso, erro)
+    SELECT id, sorteio, destino, sorteado, sucesso, erro FROM envios;
+
+DROP TABLE envios;
+
+ALTER TABLE envios_novo RENAME TO envios;
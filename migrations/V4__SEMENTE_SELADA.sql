@@ -0,0 +1,18 @@
+CREATE TABLE sorteios_novo (
+    id INTEGER PRIMARY KEY NOT NULL,
+    seed TEXT,
+    seed_cifrada BLOB,
+    compromisso TEXT,
+    jogadores_hash TEXT NOT NULL,
+    jogadores_qtd INTEGER NOT NULL,
+    jogo INTEGER REFERENCES jogos (id) ON DELETE CASCADE,
+    sal TEXT,
+    verificador BLOB
+);
+
+INSERT INTO sorteios_novo (id, seed, jogadores_hash, jogadores_qtd, jogo, sal, verificador)
+    SELECT id, seed, jogadores_hash, jogadores_qtd, jogo, sal, verificador FROM sorteios;
+
+DROP TABLE sorteios;
+
+ALTER TABLE sorteios_novo RENAME TO sorteios;
@@ -0,0 +1 @@
+ALTER TABLE jogadores ADD COLUMN pgp_public_key TEXT;
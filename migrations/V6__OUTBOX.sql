@@ -0,0 +1,12 @@
+CREATE TABLE outbox (
+    id INTEGER PRIMARY KEY NOT NULL,
+    sorteio INTEGER REFERENCES sorteios (id) ON DELETE CASCADE NOT NULL,
+    destino INTEGER REFERENCES jogadores (id) ON DELETE CASCADE NOT NULL,
+    sorteado_cifrado BLOB NOT NULL,
+    remetente TEXT NOT NULL,
+    destinatario TEXT NOT NULL,
+    mensagem_cifrada BLOB NOT NULL,
+    tentativas INTEGER NOT NULL DEFAULT 0,
+    proxima_tentativa TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ultimo_erro TEXT
+);
@@ -0,0 +1 @@
+ALTER TABLE sorteios ADD COLUMN agendado_para TEXT;
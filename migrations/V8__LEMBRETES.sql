@@ -0,0 +1,3 @@
+ALTER TABLE jogos ADD COLUMN data_evento TEXT;
+ALTER TABLE envios ADD COLUMN tipo TEXT NOT NULL DEFAULT 'sorteio';
+ALTER TABLE outbox ADD COLUMN tipo TEXT NOT NULL DEFAULT 'sorteio';
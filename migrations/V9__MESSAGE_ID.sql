@@ -0,0 +1,4 @@
+ALTER TABLE envios ADD COLUMN message_id TEXT;
+ALTER TABLE outbox ADD COLUMN message_id TEXT;
+
+CREATE INDEX envios_message_id ON envios (message_id);
@@ -1,11 +1,25 @@
 pub mod jogo {
-    use std::path::PathBuf;
+    use std::{
+        collections::{BTreeSet, HashMap},
+        path::PathBuf,
+    };
 
+    use chrono::NaiveDate;
     use rusqlite::Connection;
 
     use crate::{
-        cli::JogoFromFormat,
-        import::{csv::CsvImporter, Importer},
+        cli::{EscolhaEmail, JogoExportFormat, JogoFromFormat, JogoSetParams},
+        config::Config,
+        cripto::Chaveiro,
+        export::Exportacao,
+        import::{
+            csv::CsvImporter,
+            json::JsonImporter,
+            planilha::{OdsImporter, XlsxImporter},
+            vcf::VcfImporter,
+            yaml::YamlImporter,
+            Destino, Importer, Opcoes,
+        },
     };
 
     pub fn jogo_ls(conn: &mut Connection) {
@@ -30,25 +44,210 @@ pub mod jogo {
         tracing::info!("Deletado jogo de id {}", id)
     }
 
-    pub fn jogo_from(conn: &mut Connection, format: JogoFromFormat, path: PathBuf, nome: String) {
-        match format {
-            JogoFromFormat::Csv => CsvImporter::from_path(path, conn, &nome),
+    pub fn jogo_set(conn: &mut Connection, id: u64, param: JogoSetParams) {
+        match param {
+            JogoSetParams::Nome { val } => {
+                crate::db::jogo::update_nome(conn, id, &val);
+                tracing::info!("Atualizado o nome do jogo {id}");
+            }
+            JogoSetParams::DataEvento { val: None } => {
+                crate::db::jogo::update_data_evento(conn, id, None);
+                tracing::info!("Removida a data do evento do jogo {id}");
+            }
+            JogoSetParams::DataEvento { val: Some(val) } => {
+                if let Err(e) = NaiveDate::parse_from_str(&val, "%Y-%m-%d") {
+                    tracing::error!("Data `{val}` inválida, use AAAA-MM-DD: {e}");
+                    return;
+                }
+
+                crate::db::jogo::update_data_evento(conn, id, Some(&val));
+                tracing::info!("Atualizada a data do evento do jogo {id} para {val}");
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn jogo_from(
+        conn: &mut Connection,
+        format: JogoFromFormat,
+        path: PathBuf,
+        nome: Option<String>,
+        into: Option<u64>,
+        colunas: Vec<String>,
+        sem_cabecalho: bool,
+        email: EscolhaEmail,
+        planilha: Option<String>,
+        simulacao: bool,
+    ) {
+        if path.as_os_str() == "-" && matches!(email, EscolhaEmail::Perguntar) {
+            tracing::error!(
+                "--email perguntar precisa do terminal, que `-p -` ocupa com o arquivo"
+            );
+            return;
+        }
+
+        let mut opcoes = Opcoes {
+            sem_cabecalho,
+            email,
+            planilha,
+            ..Default::default()
+        };
+        for c in colunas {
+            let Some((campo, coluna)) = c.split_once('=') else {
+                tracing::error!("--map `{c}` inválido, use `campo=coluna`");
+                return;
+            };
+            match campo.parse() {
+                Ok(campo) => opcoes.colunas.push((campo, coluna.to_owned())),
+                Err(e) => {
+                    tracing::error!("--map `{c}` inválido: {e}");
+                    return;
+                }
+            }
+        }
+
+        let destino = match (&nome, into) {
+            (_, Some(jogo)) => {
+                if !crate::db::jogo::exists_jogo(conn, jogo) {
+                    tracing::error!("O jogo {jogo} não existe");
+                    return;
+                }
+                Destino::Existente(jogo)
+            }
+            (nome, None) => Destino::Novo(nome.as_deref()),
+        };
+
+        let resumo = match format {
+            JogoFromFormat::Csv => CsvImporter::from_path(path, &opcoes, conn, destino, simulacao),
+            JogoFromFormat::Json => {
+                JsonImporter::from_path(path, &opcoes, conn, destino, simulacao)
+            }
+            JogoFromFormat::Yaml => {
+                YamlImporter::from_path(path, &opcoes, conn, destino, simulacao)
+            }
+            JogoFromFormat::Vcf => VcfImporter::from_path(path, &opcoes, conn, destino, simulacao),
+            JogoFromFormat::Xlsx => {
+                XlsxImporter::from_path(path, &opcoes, conn, destino, simulacao)
+            }
+            JogoFromFormat::Ods => OdsImporter::from_path(path, &opcoes, conn, destino, simulacao),
+        };
+        let resumo = match resumo {
+            Ok(r) => r,
+            Err(problemas) => {
+                for p in &problemas {
+                    tracing::error!("{p}");
+                }
+                tracing::error!(
+                    "{} problema(s) no arquivo; nada foi importado",
+                    problemas.len()
+                );
+                return;
+            }
+        };
+
+        if !resumo.ja_na_base.is_empty() {
+            tracing::info!(
+                "{} jogador(es) do arquivo já estão na base:",
+                resumo.ja_na_base.len()
+            );
+            for linha in &resumo.ja_na_base {
+                tracing::info!("{linha}");
+            }
+        }
+        tracing::info!(
+            "Jogo {}: {} jogadores adicionados, {} atualizados, {} inalterados",
+            resumo.jogo,
+            resumo.adicionados.len(),
+            resumo.atualizados.len(),
+            resumo.inalterados.len()
+        );
+        if simulacao {
+            tracing::warn!("Simulação (--dry-run): nada foi gravado");
+        }
+    }
+
+    pub fn jogo_export(
+        conn: &mut Connection,
+        ctx: &Config,
+        id: u64,
+        format: JogoExportFormat,
+        path: PathBuf,
+        reveal: bool,
+    ) {
+        if !crate::db::jogo::exists_jogo(conn, id) {
+            tracing::error!("O jogo {id} não existe");
+            return;
+        }
+
+        let exportacao = Exportacao::coletar(conn, id);
+
+        let mut sorteados = HashMap::new();
+        let envios = exportacao.envios();
+        if reveal {
+            // sorteios cifrados com outra senha ficam de fora, sem pânico e
+            // sem entrar no registro de revelações
+            let mut chaveiro = Chaveiro::new(ctx);
+            let mut outra_senha = BTreeSet::new();
+            let mut abertos = vec![];
+            for e in envios {
+                if !outra_senha.contains(&e.sorteio) && chaveiro.abre(conn, e.sorteio) {
+                    abertos.push(e);
+                } else {
+                    outra_senha.insert(e.sorteio);
+                }
+            }
+
+            if !outra_senha.is_empty() {
+                tracing::warn!(
+                    "A senha não abre os sorteios {:?}; eles serão exportados sem os sorteados",
+                    outra_senha
+                );
+            }
+
+            if super::envio::pode_revelar(conn, ctx, reveal, &abertos, "jogo export") {
+                for e in &abertos {
+                    sorteados.insert(e.id, chaveiro.sorteado(conn, e));
+                }
+            }
+        }
+
+        let conteudo = match format {
+            JogoExportFormat::Csv => crate::export::csv(&exportacao, &sorteados),
+            JogoExportFormat::Json => crate::export::json(&exportacao, &sorteados),
         };
+
+        if let Err(e) = crate::export::escrever_saida(&path, &conteudo) {
+            tracing::error!("{e}");
+            return;
+        }
+
+        tracing::info!(
+            "Jogo {id} exportado para {}: {} jogadores, {} sorteios",
+            path.display(),
+            exportacao.jogadores.len(),
+            exportacao.sorteios.len()
+        );
     }
 
     #[tracing::instrument(skip_all)]
-    pub fn jogo_inspect(conn: &mut Connection, id: u64) {
+    pub fn jogo_inspect(conn: &mut Connection, ctx: &Config, id: u64) {
         let jogo = crate::db::jogo::get_jogo_by_id(conn, id);
 
         tracing::info!("{:#?}", jogo);
 
         super::jogador::jogadores_ls_with_jogo(conn, jogo.id);
-        super::sorteio::sorteios_ls_by_jogo(conn, jogo.id);
+        for (jogador, excluido) in crate::db::exclusoes::get_exclusoes_by_jogo(conn, jogo.id) {
+            tracing::info!("Exclusão: id {jogador} não tira id {excluido}");
+        }
+        super::sorteio::sorteios_ls_by_jogo(conn, ctx, jogo.id);
     }
 }
 
 pub mod jogador {
+    use std::path::PathBuf;
+
     use crate::cli::JogadoresSetParams;
+    use pgp::types::KeyDetails;
     use rusqlite::Connection;
 
     pub fn jogadores_ls_with_jogo(conn: &mut Connection, jogo: u64) {
@@ -82,6 +281,7 @@ pub mod jogador {
         let (collumn, new_value) = match param {
             JogadoresSetParams::Email { val } => ("email".to_string(), val),
             JogadoresSetParams::Nome { val } => ("nome".to_string(), val),
+            JogadoresSetParams::PgpKey { path } => return jogadores_set_pgp_key(conn, id, path),
         };
 
         crate::db::jogador::update_jogador_by_collumn(conn, &collumn, new_value, id);
@@ -89,6 +289,33 @@ pub mod jogador {
         tracing::info!("Atualizada propriedade {collumn} do id {id}");
     }
 
+    fn jogadores_set_pgp_key(conn: &mut Connection, id: u64, path: Option<PathBuf>) {
+        let Some(path) = path else {
+            crate::db::jogador::update_pgp_public_key(conn, id, None);
+            tracing::info!("Removida a chave PGP do id {id}");
+            return;
+        };
+
+        let armored = match std::fs::read_to_string(&path) {
+            Ok(a) => a,
+            Err(e) => {
+                tracing::error!("Não foi possível ler a chave PGP em {:?}: {e}", path);
+                return;
+            }
+        };
+
+        match crate::openpgp::ler_chave(&armored) {
+            Ok(chave) => {
+                crate::db::jogador::update_pgp_public_key(conn, id, Some(armored));
+                tracing::info!(
+                    "Atualizada chave PGP do id {id} (impressão digital {})",
+                    chave.fingerprint()
+                );
+            }
+            Err(e) => tracing::error!("Chave PGP inválida em {:?}: {e}", path),
+        }
+    }
+
     pub fn jogadores_rm(conn: &mut Connection, id: u64) {
         let id = crate::db::jogador::delete_jogador_by_id(conn, id);
 
@@ -99,15 +326,27 @@ pub mod jogador {
 pub mod sorteio {
     use std::hash::Hash;
 
-    use rand::{distributions::Alphanumeric, Rng, SeedableRng};
+    use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Utc};
+    use rand::{distributions::Alphanumeric, seq::SliceRandom, Rng, SeedableRng};
     use rand_chacha::ChaCha20Rng;
     use rusqlite::Connection;
 
-    use crate::config::Config;
+    use crate::{
+        config::Config,
+        cripto::Chaveiro,
+        db::{Sorteio, SorteioCego, TipoEnvio},
+        envio::{Conteudo, ProcessoEnvio},
+    };
 
-    pub fn sorteio_new(conn: &mut Connection, jogo: u64) {
+    pub fn sorteio_new(conn: &mut Connection, ctx: &Config, jogo: u64, selar: bool) {
         let seed = create_seed();
-        tracing::info!("Sorteada semente {}", seed);
+        let compromisso = crate::cripto::compromisso(&seed);
+
+        if ctx.modo_cego || selar {
+            tracing::info!("Sorteada semente com hash {}", compromisso);
+        } else {
+            tracing::info!("Sorteada semente {}", seed);
+        }
 
         let jogadores = crate::db::jogador::get_jogadores_by_jogo(conn, jogo);
         let mut jogadores_ids = jogadores.iter().map(|x| x.id).collect::<Vec<u64>>();
@@ -116,7 +355,27 @@ pub mod sorteio {
         let mut hasher = std::hash::DefaultHasher::new();
         jogadores_ids.hash(&mut hasher);
 
-        let id = crate::db::sorteio::create_sorteio(conn, &seed, jogo, hasher, jogadores);
+        let semente_em_claro = if selar { None } else { Some(&seed) };
+        let id = crate::db::sorteio::create_sorteio(
+            conn,
+            semente_em_claro,
+            &compromisso,
+            jogo,
+            hasher,
+            jogadores,
+        );
+
+        if selar {
+            let chave = Chaveiro::new(ctx).chave(conn, id as u64);
+            crate::db::sorteio::update_semente(
+                conn,
+                id as u64,
+                None,
+                Some(chave.cifrar(seed.as_bytes())),
+                &compromisso,
+            );
+            tracing::info!("Semente selada com a senha do organizador");
+        }
 
         tracing::info!("Criado sorteio com id {id}");
         tracing::info!("Use o comando `sorteio run` para rodá-lo");
@@ -130,15 +389,64 @@ pub mod sorteio {
             .collect::<String>()
     }
 
-    pub fn sorteio_run(conn: &mut Connection, id: u64, smtp_ctx: &Config) {
+    pub fn sorteio_run(conn: &mut Connection, id: u64, smtp_ctx: &Config, at: Option<String>) {
+        let agendado_para = match at.as_deref().map(ler_horario) {
+            None => None,
+            Some(Ok(horario)) => Some(horario),
+            Some(Err(e)) => {
+                tracing::error!("Horário de agendamento inválido: {e}");
+                return;
+            }
+        };
+
         let sorteio = crate::db::sorteio::get_sorteio_by_id(conn, &id);
         let jogadores = crate::db::jogador::get_jogadores_by_jogo(conn, sorteio.jogo);
 
-        let ids = crate::db::envios::delete_envios_by_sorteio(conn, sorteio.id);
+        let mut chaveiro = Chaveiro::new(smtp_ctx);
+        let Some(seed) = chaveiro.semente(conn, &sorteio) else {
+            tracing::error!(
+                "A semente do sorteio {} foi descartada; ele não pode ser rodado novamente",
+                sorteio.id
+            );
+            return;
+        };
 
+        let exclusoes = crate::db::exclusoes::get_exclusoes_by_jogo(conn, sorteio.jogo);
+        let mut rand = crate::envio::gerador(&seed);
+        let pares = match crate::envio::sortear(&mut rand, jogadores, &exclusoes) {
+            Ok(pares) => pares,
+            Err(e) => {
+                tracing::error!("Não foi possível sortear: {e}");
+                return;
+            }
+        };
+
+        let ids = crate::db::envios::delete_envios_by_sorteio(conn, sorteio.id);
         tracing::warn!("Deletados envios com ids {:?}", ids);
 
-        let _ = crate::envio::run_and_email(sorteio, jogadores, smtp_ctx, conn);
+        let ids = crate::db::outbox::delete_itens_by_sorteio(conn, sorteio.id);
+        if !ids.is_empty() {
+            tracing::warn!("Retirados da fila itens com ids {:?}", ids);
+        }
+
+        crate::db::sorteio::update_agendamento(
+            conn,
+            id,
+            agendado_para
+                .map(|h| h.format(crate::db::FORMATO_DATA).to_string())
+                .as_ref(),
+        );
+
+        let ids = crate::envio::run_and_email(
+            sorteio,
+            pares,
+            &mut rand,
+            smtp_ctx,
+            conn,
+            &mut chaveiro,
+            agendado_para,
+        );
+        crate::organizador::avisar(conn, smtp_ctx, &ids);
 
         for r in crate::db::envios::get_envios_by_sorteio(conn, id) {
             if r.sucesso {
@@ -151,81 +459,1076 @@ pub mod sorteio {
                 )
             }
         }
+
+        let pendentes = crate::db::outbox::get_itens_by_sorteio(conn, id);
+        if let Some(horario) = agendado_para {
+            tracing::info!(
+                "{} mensagens agendadas para {}; deixe o `worker --daemon` rodando para enviá-las",
+                pendentes.len(),
+                horario.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            );
+            return;
+        }
+
+        for item in &pendentes {
+            tracing::warn!(
+                "Envio para id {} continua na fila: {:?}",
+                item.destino,
+                item.ultimo_erro
+            )
+        }
+        if !pendentes.is_empty() {
+            tracing::warn!(
+                "{} mensagens continuam na fila; rode `worker` para entregá-las",
+                pendentes.len()
+            )
+        }
     }
 
-    pub fn sorteios_ls_by_jogo(conn: &mut Connection, jogo: u64) {
+    pub fn sorteios_ls_by_jogo(conn: &mut Connection, ctx: &Config, jogo: u64) {
         let sorteios = crate::db::sorteio::get_sorteios_by_jogo(conn, jogo);
 
         for s in sorteios {
-            tracing::info!("{:?}", s)
+            print_sorteio(conn, ctx, &s)
         }
     }
 
-    pub fn sorteio_ls(conn: &mut Connection) {
+    pub fn sorteio_ls(conn: &mut Connection, ctx: &Config) {
         let sorteios = crate::db::sorteio::get_sorteios(conn);
 
         for s in sorteios {
-            tracing::info!("{:?}", s)
+            print_sorteio(conn, ctx, &s)
+        }
+    }
+
+    pub fn sorteio_inspect(conn: &mut Connection, ctx: &Config, id: u64) {
+        let sorteio = crate::db::sorteio::get_sorteio_by_id(conn, &id);
+
+        if ctx.modo_cego {
+            tracing::info!("{:#?}", SorteioCego(&sorteio))
+        } else {
+            tracing::info!("{:#?}", sorteio)
+        }
+    }
+
+    fn print_sorteio(conn: &mut Connection, ctx: &Config, sorteio: &Sorteio) {
+        if ctx.modo_cego {
+            tracing::info!("{:?}", SorteioCego(sorteio))
+        } else {
+            tracing::info!("{:?}", sorteio)
+        }
+
+        if let Some(agendado_para) = &sorteio.agendado_para {
+            let pendentes = crate::db::outbox::get_itens_by_sorteio(conn, sorteio.id).len();
+            if pendentes > 0 {
+                tracing::info!(
+                    "  agendado para {} com {} mensagens pendentes",
+                    horario_local(agendado_para),
+                    pendentes
+                );
+            }
+        }
+    }
+
+    /// Lê um horário local no formato `AAAA-MM-DD HH:MM`, que precisa estar no futuro
+    fn ler_horario(texto: &str) -> Result<DateTime<Utc>, String> {
+        let horario = NaiveDateTime::parse_from_str(texto, "%Y-%m-%d %H:%M")
+            .map_err(|e| format!("`{texto}` não está no formato AAAA-MM-DD HH:MM ({e})"))?
+            .and_local_timezone(Local)
+            .single()
+            .ok_or(format!("`{texto}` é ambíguo ou não existe no fuso local"))?
+            .with_timezone(&Utc);
+
+        if horario <= Utc::now() {
+            return Err(format!("`{texto}` já passou"));
+        }
+        Ok(horario)
+    }
+
+    /// Converte um horário guardado na base (UTC) para o fuso local
+    fn horario_local(guardado: &str) -> String {
+        NaiveDateTime::parse_from_str(guardado, crate::db::FORMATO_DATA)
+            .unwrap()
+            .and_utc()
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    }
+
+    pub fn sorteio_seal(conn: &mut Connection, ctx: &Config, id: u64) {
+        let sorteio = crate::db::sorteio::get_sorteio_by_id(conn, &id);
+
+        let Some(seed) = sorteio.seed else {
+            tracing::warn!("A semente do sorteio {id} já está selada ou foi descartada");
+            return;
+        };
+
+        let chave = Chaveiro::new(ctx).chave(conn, id);
+        crate::db::sorteio::update_semente(
+            conn,
+            id,
+            None,
+            Some(chave.cifrar(seed.as_bytes())),
+            &crate::cripto::compromisso(&seed),
+        );
+
+        tracing::info!("Semente do sorteio {id} selada com a senha do organizador");
+    }
+
+    pub fn sorteio_discard_seed(conn: &mut Connection, id: u64) {
+        let sorteio = crate::db::sorteio::get_sorteio_by_id(conn, &id);
+
+        if sorteio.seed.is_none() && sorteio.seed_cifrada.is_none() {
+            tracing::warn!("A semente do sorteio {id} já foi descartada");
+            return;
+        }
+
+        let envios = crate::db::envios::get_envios_by_sorteio(conn, id);
+        let falhas = envios.iter().filter(|e| !e.sucesso).count();
+
+        if envios.is_empty() {
+            tracing::warn!("O sorteio {id} ainda não foi rodado!");
+        } else if falhas > 0 {
+            tracing::warn!("{falhas} envio(s) do sorteio {id} falharam!");
+        }
+        tracing::warn!("Sem a semente, `sorteio run` não poderá ser refeito para este sorteio");
+        tracing::warn!(
+            "`envio redo` continua usando os envios cifrados; se eles forem apagados, os pares não podem mais ser recuperados"
+        );
+
+        if !crate::prompt::confirmar("Descartar a semente?", "descartar") {
+            tracing::info!("Nada foi alterado");
+            return;
+        }
+
+        let compromisso = sorteio.compromisso.unwrap_or_else(|| {
+            // sorteios antigos ainda com semente em claro, sem hash guardado
+            crate::cripto::compromisso(sorteio.seed.as_ref().unwrap())
+        });
+        crate::db::sorteio::update_semente(conn, id, None, None, &compromisso);
+
+        tracing::info!("Semente descartada; hash guardado: {compromisso}");
+    }
+
+    pub fn sorteio_verify(conn: &mut Connection, id: u64, seed: String) {
+        let sorteio = crate::db::sorteio::get_sorteio_by_id(conn, &id);
+
+        match sorteio.compromisso {
+            Some(c) if c == crate::cripto::compromisso(&seed) => {
+                tracing::info!("A semente confere com o sorteio {id}")
+            }
+            Some(_) => tracing::error!("A semente NÃO confere com o sorteio {id}"),
+            None => tracing::warn!("O sorteio {id} não tem hash da semente guardado"),
         }
     }
 
-    pub fn sorteio_inspect(conn: &mut Connection, id: u64) {
+    /// Manda a corrente completa a todos os jogadores do sorteio.
+    ///
+    /// Só é permitido depois da data do evento e uma única vez, a não ser com
+    /// `force`. Cada revelação entregue fica registrada na tabela
+    /// `revelacoes`, inclusive as que o `worker` entregar depois
+    pub fn sorteio_reveal(conn: &mut Connection, ctx: &Config, id: u64, force: bool) {
         let sorteio = crate::db::sorteio::get_sorteio_by_id(conn, &id);
+        let jogo = crate::db::jogo::get_jogo_by_id(conn, sorteio.jogo);
+        let envios = crate::db::envios::get_envios_by_sorteio(conn, id);
+
+        let data_evento = jogo
+            .data_evento
+            .as_ref()
+            .map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap());
+        let liberado = data_evento.is_some_and(|d| Local::now().date_naive() > d);
+        let ja_revelado = envios.iter().any(|e| e.tipo == TipoEnvio::Revelacao);
+
+        if !force {
+            match data_evento {
+                None => {
+                    tracing::error!("O jogo {} não tem data do evento; defina-a com `jogo set {} data-evento` ou use --force", jogo.id, jogo.id);
+                    return;
+                }
+                Some(d) if !liberado => {
+                    tracing::error!("A corrente só pode ser revelada depois do evento ({d}); use --force para revelar antes");
+                    return;
+                }
+                _ if ja_revelado => {
+                    tracing::error!("A corrente do sorteio {id} já foi revelada; use --force para mandá-la de novo");
+                    return;
+                }
+                _ => (),
+            }
+        } else {
+            if !liberado {
+                tracing::warn!("O evento ainda não aconteceu!");
+            }
+            if ja_revelado {
+                tracing::warn!("A corrente do sorteio {id} já foi revelada antes");
+            }
+            if !crate::prompt::confirmar(
+                "Isso vai mandar a todos os jogadores quem tirou quem.",
+                "revelar",
+            ) {
+                tracing::info!("Nada foi enviado");
+                return;
+            }
+        }
+
+        let mut chaveiro = Chaveiro::new(ctx);
+        let chave = chaveiro.chave(conn, id);
+        let pares = crate::envio::corrente(conn, &mut chaveiro, id);
+        if pares.is_empty() {
+            tracing::error!("O sorteio {id} ainda não foi rodado");
+            return;
+        }
+
+        let comando = if force {
+            "sorteio reveal --force"
+        } else {
+            "sorteio reveal"
+        };
+        let mut processos = pares
+            .iter()
+            .map(|(destino, sorteado)| ProcessoEnvio {
+                destino: destino.clone(),
+                sorteado: sorteado.clone(),
+                sorteio: id,
+                conteudo: Conteudo::Revelacao(pares.clone()),
+            })
+            .collect::<Vec<_>>();
+        processos.shuffle(&mut rand::thread_rng());
+
+        let (itens, erros) = crate::envio::enfileirar(processos, ctx, conn, &chave, None);
+        let entregas = crate::envio::drenar(ctx, conn, &mut chaveiro, Some(&itens));
+        crate::organizador::avisar(
+            conn,
+            ctx,
+            &[
+                erros.clone(),
+                entregas.iter().filter_map(|(_, e)| *e).collect(),
+            ]
+            .concat(),
+        );
+
+        let registrados = entregas
+            .into_iter()
+            .filter_map(|(_, envio)| envio)
+            .collect::<Vec<_>>();
+        let sucessos = super::envio::registrar_revelacoes(conn, &registrados, comando);
 
-        tracing::info!("{:#?}", sorteio)
+        tracing::info!(
+            "Corrente enviada a {} de {} jogadores; {} com erro, {} ainda na fila",
+            sucessos,
+            pares.len(),
+            erros.len() + registrados.len() - sucessos,
+            itens.len() - registrados.len()
+        );
     }
 }
 
 pub mod envio {
+    use std::path::PathBuf;
+
+    use chrono::NaiveDate;
     use rusqlite::Connection;
 
     use crate::{
         config::Config,
-        envio::{make_transport, ProcessoEnvio},
+        cripto::Chaveiro,
+        db::{Envio, EnvioCego, TipoEnvio},
+        envio::{drenar, enfileirar, Conteudo, ProcessoEnvio},
     };
 
-    pub fn envio_inspect(conn: &mut Connection, envio: u64) {
+    pub fn envio_inspect(conn: &mut Connection, ctx: &Config, envio: u64, reveal: bool) {
         let envio = crate::db::envios::get_envio_by_id(conn, envio);
 
-        tracing::info!("{:?}", envio);
+        if pode_revelar(
+            conn,
+            ctx,
+            reveal,
+            std::slice::from_ref(&envio),
+            "envio inspect",
+        ) {
+            let mut chaveiro = Chaveiro::new(ctx);
+            tracing::info!("{}", revelado(conn, &mut chaveiro, envio));
+        } else {
+            tracing::info!("{:?}", EnvioCego(&envio));
+        }
+    }
+
+    /// O envio com o sorteado decifrado e o nome de quem foi tirado
+    fn revelado(conn: &mut Connection, chaveiro: &mut Chaveiro, mut envio: Envio) -> String {
+        let sorteado = chaveiro.sorteado(conn, &envio);
+        envio.sorteado = Some(sorteado);
+        let nome = crate::db::jogador::get_jogador_by_id(conn, sorteado).nome;
+
+        format!("{envio:?}, tirou {nome}")
     }
 
     pub fn envio_redo(conn: &mut Connection, ctx: &Config, envio: u64) {
         let envio = crate::db::envios::get_envio_by_id(conn, envio);
+        let mut chaveiro = Chaveiro::new(ctx);
+        let chave = chaveiro.chave(conn, envio.sorteio);
+        let sorteado = chaveiro.sorteado(conn, &envio);
+
         let destino = crate::db::jogador::get_jogador_by_id(conn, envio.destino);
-        let sorteado = crate::db::jogador::get_jogador_by_id(conn, envio.sorteado);
-        let transport = make_transport(ctx);
+        let sorteado = crate::db::jogador::get_jogador_by_id(conn, sorteado);
+
+        let conteudo = match envio.tipo {
+            TipoEnvio::Sorteio => Conteudo::Sorteio,
+            TipoEnvio::Lembrete => {
+                let sorteio = crate::db::sorteio::get_sorteio_by_id(conn, &envio.sorteio);
+                let jogo = crate::db::jogo::get_jogo_by_id(conn, sorteio.jogo);
+                let Some(data) = jogo.data_evento else {
+                    tracing::error!("O jogo {} não tem mais data do evento", jogo.id);
+                    return;
+                };
+                Conteudo::Lembrete(NaiveDate::parse_from_str(&data, "%Y-%m-%d").unwrap())
+            }
+            TipoEnvio::Revelacao => {
+                Conteudo::Revelacao(crate::envio::corrente(conn, &mut chaveiro, envio.sorteio))
+            }
+        };
 
         let processo = ProcessoEnvio {
             destino,
             sorteado,
             sorteio: envio.sorteio,
+            conteudo,
         };
 
-        let id = processo.enviar(transport, conn, ctx);
+        let (itens, erros) = enfileirar(vec![processo], ctx, conn, &chave, None);
+        let id = match erros.first() {
+            Some(id) => *id,
+            None => {
+                let item = itens[0];
+                let entregas = drenar(ctx, conn, &mut chaveiro, Some(&itens));
+                crate::organizador::avisar(
+                    conn,
+                    ctx,
+                    &entregas.iter().filter_map(|(_, e)| *e).collect::<Vec<_>>(),
+                );
+
+                let entrega = entregas
+                    .into_iter()
+                    .find(|(i, _)| *i == item)
+                    .and_then(|(_, envio)| envio);
+
+                let Some(id) = entrega else {
+                    tracing::warn!(
+                        "O envio continua na fila como item {}; rode `worker` para entregá-lo",
+                        item
+                    );
+                    return;
+                };
+                id
+            }
+        };
+        if !erros.is_empty() {
+            crate::organizador::avisar(conn, ctx, &erros);
+        }
+        registrar_revelacoes(conn, &[id], "envio redo");
         let new_envio = crate::db::envios::get_envio_by_id(conn, id.try_into().unwrap());
 
         tracing::info!("Criado um novo envio com id {}", id);
         if new_envio.sucesso {
-            tracing::info!("Envio exitoso: {:#?}", new_envio)
+            tracing::info!("Envio exitoso: {:#?}", EnvioCego(&new_envio))
         } else {
-            tracing::error!("Erro! {:#?}", new_envio)
+            tracing::error!("Erro! {:#?}", EnvioCego(&new_envio))
         }
     }
 
-    pub fn envio_ls_all(conn: &mut Connection) {
-        let envio = crate::db::envios::get_all_envios(conn);
+    pub fn envio_bounces(conn: &mut Connection, maildir: Option<PathBuf>, mbox: Option<PathBuf>) {
+        let mensagens = match (&maildir, &mbox) {
+            (Some(path), _) => crate::bounces::ler_maildir(path),
+            (None, Some(path)) => crate::bounces::ler_mbox(path),
+            (None, None) => unreachable!(),
+        };
+        let mensagens = match mensagens {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::error!("Não foi possível ler a caixa de mensagens: {e}");
+                return;
+            }
+        };
 
-        for e in envio {
-            tracing::info!("{:?}", e)
+        let bounces = mensagens
+            .iter()
+            .filter_map(|m| crate::bounces::analisar(m))
+            .collect::<Vec<_>>();
+        let mut marcados = 0;
+
+        for b in &bounces {
+            let Some(envio) = crate::db::envios::get_envio_by_message_id(conn, &b.message_id)
+            else {
+                tracing::debug!("Bounce de {} não corresponde a nenhum envio", b.message_id);
+                continue;
+            };
+
+            if envio.sucesso || envio.erro.as_deref() != Some(b.motivo.as_str()) {
+                crate::db::envios::update_bounce(conn, envio.id, &b.motivo);
+                tracing::warn!(
+                    "Envio {} para id {} devolvido: {}",
+                    envio.id,
+                    envio.destino,
+                    b.motivo
+                );
+                marcados += 1;
+            }
         }
+
+        tracing::info!(
+            "{} mensagens lidas, {} bounces, {} envios marcados como falhos",
+            mensagens.len(),
+            bounces.len(),
+            marcados
+        );
+    }
+
+    pub fn envio_ls_all(conn: &mut Connection, ctx: &Config, reveal: bool) {
+        let envios = crate::db::envios::get_all_envios(conn);
+
+        print_envios(conn, ctx, envios, reveal, "envio ls");
     }
 
-    pub fn envio_ls_with_sorteio(conn: &mut Connection, sorteio: u64) {
+    pub fn envio_ls_with_sorteio(conn: &mut Connection, ctx: &Config, sorteio: u64, reveal: bool) {
         let envios = crate::db::envios::get_envios_by_sorteio(conn, sorteio);
 
-        for e in envios {
+        print_envios(conn, ctx, envios, reveal, "envio ls --sorteio");
+
+        let pendentes = crate::db::envios::get_destinos_sem_confirmacao(conn, sorteio);
+        if !pendentes.is_empty() {
+            let nomes = pendentes
+                .into_iter()
+                .map(|id| {
+                    let j = crate::db::jogador::get_jogador_by_id(conn, id);
+                    format!("{} <{}>", j.nome, j.email)
+                })
+                .collect::<Vec<_>>();
+            tracing::warn!(
+                "{} ainda não confirmaram o recebimento: {}",
+                nomes.len(),
+                nomes.join(", ")
+            );
+        }
+    }
+
+    fn print_envios(
+        conn: &mut Connection,
+        ctx: &Config,
+        envios: Vec<Envio>,
+        reveal: bool,
+        comando: &str,
+    ) {
+        if !pode_revelar(conn, ctx, reveal, &envios, comando) {
+            for e in envios {
+                tracing::info!("{:?}", EnvioCego(&e))
+            }
+            return;
+        }
+
+        let mut chaveiro = Chaveiro::new(ctx);
+        for mut e in envios {
+            e.sorteado = Some(chaveiro.sorteado(conn, &e));
             tracing::info!("{:?}", e)
         }
     }
+
+    /// Decide se os sorteados podem ser mostrados.
+    ///
+    /// Fora do modo cego, ou com `--reveal` confirmado, todo envio mostrado
+    /// fica registrado na tabela `revelacoes`
+    pub(super) fn pode_revelar(
+        conn: &mut Connection,
+        ctx: &Config,
+        reveal: bool,
+        envios: &[Envio],
+        comando: &str,
+    ) -> bool {
+        if ctx.modo_cego && !reveal {
+            return false;
+        }
+
+        if ctx.modo_cego
+            && !crate::prompt::confirmar(
+                &format!(
+                    "Isso vai mostrar quem tirou quem em {} envio(s).",
+                    envios.len()
+                ),
+                "revelar",
+            )
+        {
+            tracing::warn!("Revelação cancelada, mostrando envios sem o sorteado");
+            return false;
+        }
+
+        for e in envios {
+            crate::db::revelacoes::create_revelacao(conn, e.id, e.sorteio, comando);
+        }
+
+        tracing::warn!("Revelação de {} envio(s) registrada", envios.len());
+        true
+    }
+
+    /// Registra em `revelacoes` as revelações da corrente que foram de fato
+    /// entregues; as que falharam ou continuam na fila não mostraram nada a
+    /// ninguém. Outros tipos de envio são ignorados.
+    ///
+    /// Retorna quantas foram registradas
+    pub(super) fn registrar_revelacoes(
+        conn: &mut Connection,
+        envios: &[usize],
+        comando: &str,
+    ) -> usize {
+        let mut registradas = 0;
+        for id in envios {
+            let e = crate::db::envios::get_envio_by_id(conn, *id as u64);
+            if e.sucesso && e.tipo == TipoEnvio::Revelacao {
+                crate::db::revelacoes::create_revelacao(conn, e.id, e.sorteio, comando);
+                registradas += 1;
+            }
+        }
+        registradas
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use rusqlite::params;
+
+        use super::*;
+
+        #[test]
+        fn revelado_mostra_o_sorteado() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            crate::db::migrar(&mut conn);
+            conn.execute("INSERT INTO jogos (id, nome) VALUES (1, 'Família')", [])
+                .unwrap();
+            for (id, nome) in [(1, "Ana"), (2, "Beto")] {
+                conn.execute(
+                    "INSERT INTO jogadores (id, nome, email, jogo) VALUES (?1, ?2, ?3, 1)",
+                    params![id, nome, format!("{}@x.com", nome.to_lowercase())],
+                )
+                .unwrap();
+            }
+            conn.execute(
+                "INSERT INTO sorteios (id, seed, jogadores_hash, jogadores_qtd, jogo) VALUES (1, 's', 'h', 2, 1)",
+                [],
+            )
+            .unwrap();
+
+            let mut chaveiro = Chaveiro::com_senha("senha".to_owned());
+            let chave = chaveiro.chave(&mut conn, 1);
+            conn.execute(
+                "INSERT INTO envios (id, sorteio, destino, sorteado_cifrado, sucesso) VALUES (1, 1, 1, ?1, 1)",
+                params![chave.cifrar_sorteado(2)],
+            )
+            .unwrap();
+
+            let envio = crate::db::envios::get_envio_by_id(&mut conn, 1);
+            assert_eq!(envio.sorteado, None);
+
+            let linha = revelado(&mut conn, &mut chaveiro, envio);
+            assert!(linha.contains("sorteado: Some(2)"));
+            assert!(linha.ends_with("tirou Beto"));
+        }
+    }
+}
+
+pub mod smtp {
+    use std::{
+        net::{SocketAddr, TcpStream, ToSocketAddrs},
+        time::Duration,
+    };
+
+    use lettre::{Message, Transport};
+
+    use crate::{
+        config::Config,
+        envio::{make_transport, make_transport_tls_only},
+    };
+
+    /// Testa cada etapa do envio em ordem, parando na primeira que falhar
+    pub fn smtp_test(ctx: &Config, to: Option<Option<String>>) {
+        let Some(enderecos) = testar_dns(ctx) else {
+            return;
+        };
+        if !testar_tcp(ctx, &enderecos) {
+            return;
+        }
+        testar_tls(ctx);
+        if !testar_auth(ctx) {
+            return;
+        }
+        testar_dkim(ctx);
+
+        if let Some(to) = to {
+            testar_envio(ctx, to.unwrap_or(ctx.smtp_sender.clone()));
+        }
+    }
+
+    fn testar_dns(ctx: &Config) -> Option<Vec<SocketAddr>> {
+        match (ctx.smtp_relay.as_str(), ctx.smtp_port).to_socket_addrs() {
+            Ok(enderecos) => {
+                let enderecos = enderecos.collect::<Vec<_>>();
+                tracing::info!("DNS: {} resolve para {:?}", ctx.smtp_relay, enderecos);
+                Some(enderecos)
+            }
+            Err(e) => {
+                tracing::error!("DNS: não foi possível resolver `{}`: {e}", ctx.smtp_relay);
+                tracing::error!("Confira o nome do servidor em SMTP_RELAY");
+                None
+            }
+        }
+    }
+
+    fn testar_tcp(ctx: &Config, enderecos: &[SocketAddr]) -> bool {
+        for endereco in enderecos {
+            match TcpStream::connect_timeout(endereco, Duration::from_secs(10)) {
+                Ok(_) => {
+                    tracing::info!("TCP: conectado a {endereco}");
+                    return true;
+                }
+                Err(e) => tracing::warn!("TCP: falha ao conectar a {endereco}: {e}"),
+            }
+        }
+
+        tracing::error!(
+            "TCP: nenhum endereço aceitou conexão na porta {}",
+            ctx.smtp_port
+        );
+        tracing::error!(
+            "Confira SMTP_PORT (normalmente 587) e se a rede ou um firewall não bloqueia a porta"
+        );
+        false
+    }
+
+    /// Só avisa em caso de falha, já que o envio usa TLS oportunista
+    fn testar_tls(ctx: &Config) {
+        match make_transport_tls_only(ctx).test_connection() {
+            Ok(_) => tracing::info!("TLS: STARTTLS negociado com sucesso"),
+            Err(e) => {
+                tracing::warn!("TLS: não foi possível negociar STARTTLS: {e}");
+                tracing::warn!(
+                    "As mensagens e a senha SMTP serão enviadas sem cifragem; confira se o servidor suporta STARTTLS nesta porta"
+                );
+            }
+        }
+    }
+
+    fn testar_auth(ctx: &Config) -> bool {
+        match make_transport(ctx).test_connection() {
+            Ok(_) => {
+                tracing::info!("AUTH: autenticado como {}", ctx.smtp_username);
+                true
+            }
+            Err(e) => {
+                tracing::error!("AUTH: falha ao autenticar como {}: {e}", ctx.smtp_username);
+                tracing::error!(
+                    "Confira SMTP_USER e SMTP_PASSWORD; provedores como o Gmail exigem uma senha de app"
+                );
+                false
+            }
+        }
+    }
+
+    fn testar_dkim(ctx: &Config) {
+        let Some(dkim) = &ctx.dkim else {
+            tracing::info!(
+                "DKIM: não configurado (defina DKIM_SELECTOR, DKIM_DOMAIN e DKIM_PRIVATE_KEY)"
+            );
+            return;
+        };
+
+        let mut message = Message::builder()
+            .from(ctx.smtp_sender.parse().unwrap())
+            .to(ctx.smtp_sender.parse().unwrap())
+            .subject(ctx.subject.clone())
+            .body("Teste de assinatura DKIM".to_owned())
+            .unwrap();
+        message.sign(&dkim.config());
+
+        match dkim.verificar(&message.formatted()) {
+            Ok(()) => tracing::info!("DKIM: assinatura verificada localmente"),
+            Err(e) => tracing::error!("DKIM: {e}"),
+        }
+
+        let (nome, conteudo) = dkim.registro_dns();
+        tracing::info!("DKIM: o DNS deve ter o registro TXT `{nome}` com `{conteudo}`");
+    }
+
+    fn testar_envio(ctx: &Config, to: String) {
+        let to = match to.parse() {
+            Ok(to) => to,
+            Err(e) => {
+                tracing::error!("Envio: endereço `{to}` inválido: {e}");
+                return;
+            }
+        };
+
+        let mut message = Message::builder()
+            .from(ctx.smtp_sender.parse().unwrap())
+            .to(to)
+            .subject(format!("{} (teste)", ctx.subject))
+            .body("Se você recebeu esta mensagem, a configuração de envio está correta.".to_owned())
+            .unwrap();
+        if let Some(dkim) = &ctx.dkim {
+            message.sign(&dkim.config());
+        }
+
+        match make_transport(ctx).send(&message) {
+            Ok(r) => tracing::info!("Envio: mensagem aceita pelo servidor ({})", r.code()),
+            Err(e) => {
+                tracing::error!("Envio: mensagem recusada: {e}");
+                tracing::error!(
+                    "Confira se SMTP_SENDER é um endereço que SMTP_USER tem permissão de usar"
+                );
+            }
+        }
+    }
+}
+
+pub mod db {
+    use std::path::PathBuf;
+
+    use rusqlite::Connection;
+
+    use crate::{config::Config, db::backup};
+
+    pub fn db_backup(conn: &mut Connection, path: PathBuf) {
+        if let Err(e) = backup::salvar(conn, &path) {
+            tracing::error!(
+                "Não foi possível copiar a base para {}: {e}",
+                path.display()
+            );
+            return;
+        }
+
+        tracing::info!(
+            "Base copiada para {} (esquema {})",
+            path.display(),
+            backup::versao_embutida()
+        );
+    }
+
+    /// Restaura uma cópia; as sementes e sorteados continuam cifrados com a
+    /// senha do organizador de quando foram criados
+    pub fn db_restore(conn: &mut Connection, ctx: &Config, path: PathBuf, force: bool) {
+        let versao = match backup::verificar(&path) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::error!("{} não pode ser restaurado: {e}", path.display());
+                return;
+            }
+        };
+
+        if !force
+            && !crate::prompt::confirmar(
+                &format!(
+                    "Isso vai substituir todo o conteúdo de {} pelo de {}.",
+                    ctx.db_path,
+                    path.display()
+                ),
+                "restaurar",
+            )
+        {
+            tracing::warn!("Restauração cancelada");
+            return;
+        }
+
+        if let Err(e) = backup::restaurar(conn, &path) {
+            tracing::error!("Não foi possível restaurar {}: {e}", path.display());
+            return;
+        }
+
+        let embutida = backup::versao_embutida();
+        if versao < embutida {
+            tracing::info!("Esquema da cópia atualizado de {versao} para {embutida}");
+        }
+        tracing::info!("Base restaurada de {}", path.display());
+    }
+}
+
+pub mod archive {
+    use std::path::PathBuf;
+
+    use rusqlite::Connection;
+
+    use crate::{arquivo::Arquivo, config::Config, cripto::Chaveiro};
+
+    pub fn archive_export(conn: &mut Connection, jogos: Vec<u64>, path: PathBuf) {
+        let jogos = match jogos.is_empty() {
+            true => crate::db::jogo::get_all_jogos(conn)
+                .into_iter()
+                .map(|j| j.id)
+                .collect(),
+            false => jogos,
+        };
+
+        if let Some(j) = jogos
+            .iter()
+            .find(|j| !crate::db::jogo::exists_jogo(conn, **j))
+        {
+            tracing::error!("O jogo {j} não existe");
+            return;
+        }
+
+        let arquivo = crate::arquivo::exportar(conn, &jogos);
+        let conteudo = serde_json::to_string_pretty(&arquivo).unwrap();
+        if let Err(e) = crate::export::escrever_saida(&path, &conteudo) {
+            tracing::error!("{e}");
+            return;
+        }
+
+        tracing::info!(
+            "{} jogo(s) exportado(s) para {} (formato {}, esquema {})",
+            jogos.len(),
+            path.display(),
+            arquivo.versao,
+            arquivo.esquema
+        );
+    }
+
+    pub fn archive_import(conn: &mut Connection, ctx: &Config, path: PathBuf) {
+        let arquivo: Arquivo = match crate::import::ler_entrada(&path).and_then(|b| {
+            serde_json::from_slice(&b).map_err(|e| format!("{} é inválido: {e}", path.display()))
+        }) {
+            Ok(a) => a,
+            Err(e) => {
+                tracing::error!("{e}");
+                return;
+            }
+        };
+
+        if let Err(e) = arquivo.validar(conn) {
+            tracing::error!("{} não pode ser importado: {e}", path.display());
+            return;
+        }
+
+        let chaveiro = arquivo.cifrado().then(|| Chaveiro::new(ctx));
+        let tx = conn.transaction().unwrap();
+        let jogos = match crate::arquivo::importar(
+            &tx,
+            &arquivo,
+            chaveiro.as_ref().map(Chaveiro::senha),
+            None,
+        ) {
+            Ok(j) => j,
+            Err(e) => {
+                tracing::error!(
+                    "{} não pode ser importado: {e}; nada foi gravado",
+                    path.display()
+                );
+                return;
+            }
+        };
+        tx.commit().unwrap();
+
+        for (antigo, novo) in jogos {
+            tracing::info!("Jogo {antigo} do arquivo importado com id {novo}");
+        }
+        for (tabela, linhas) in &arquivo.tabelas {
+            tracing::debug!("{} linha(s) em {tabela}", linhas.len());
+        }
+    }
+}
+
+pub mod worker {
+    use std::time::Duration;
+
+    use rusqlite::Connection;
+
+    use crate::{config::Config, cripto::Chaveiro};
+
+    /// De quanto em quanto tempo, no máximo, o worker olha a fila de novo
+    const INTERVALO: Duration = Duration::from_secs(60);
+
+    /// Entrega o que estiver na `outbox`, esperando pelas novas tentativas,
+    /// até a fila esvaziar. Com `daemon`, não para nunca e também espera
+    /// pelas mensagens agendadas. A cada passagem, enfileira os lembretes que
+    /// já estiverem na hora.
+    ///
+    /// Um item só sai da fila na mesma transação que registra o seu envio, então
+    /// o worker pode ser interrompido e rodado de novo a qualquer momento. Se ele
+    /// morrer entre o servidor aceitar a mensagem e a transação terminar, a
+    /// mensagem é entregue de novo na próxima vez
+    pub fn worker_run(conn: &mut Connection, ctx: &Config, daemon: bool) {
+        let mut chaveiro = Chaveiro::new(ctx);
+
+        loop {
+            crate::envio::agendar_lembretes(ctx, conn, &mut chaveiro);
+
+            let entregas = crate::envio::drenar(ctx, conn, &mut chaveiro, None);
+            crate::organizador::avisar(
+                conn,
+                ctx,
+                &entregas.iter().filter_map(|(_, e)| *e).collect::<Vec<_>>(),
+            );
+            super::envio::registrar_revelacoes(
+                conn,
+                &entregas.iter().filter_map(|(_, e)| *e).collect::<Vec<_>>(),
+                "worker",
+            );
+            let registrados = entregas.iter().filter(|(_, e)| e.is_some()).count();
+            if !entregas.is_empty() {
+                tracing::info!(
+                    "{} itens processados, {} registrados em envios, {} de volta na fila",
+                    entregas.len(),
+                    registrados,
+                    entregas.len() - registrados
+                );
+            }
+
+            // itens de sorteios que a senha não abre não contam na espera
+            let proximo = if daemon {
+                crate::db::outbox::segundos_ate_proximo(conn, chaveiro.recusados())
+            } else {
+                crate::db::outbox::segundos_ate_nova_tentativa(conn, chaveiro.recusados())
+            };
+
+            let espera = match proximo {
+                Some(segundos) => Duration::from_secs(segundos.max(1)).min(INTERVALO),
+                None if daemon => INTERVALO,
+                None => {
+                    match crate::db::outbox::count_itens(conn, chaveiro.recusados()) {
+                        0 if chaveiro.recusados().is_empty() => {
+                            tracing::info!("A fila está vazia")
+                        }
+                        0 => tracing::info!("A fila não tem mais nada que esta senha abra"),
+                        n => tracing::info!(
+                            "{n} mensagens agendadas continuam na fila; use `worker --daemon` para enviá-las na hora certa"
+                        ),
+                    }
+                    return;
+                }
+            };
+
+            tracing::debug!("Olhando a fila de novo em {:?}", espera);
+            std::thread::sleep(espera);
+        }
+    }
+}
+
+pub mod serve {
+    use rusqlite::Connection;
+    use tiny_http::{Header, Method, Request, Response, Server};
+
+    use crate::config::Config;
+
+    /// Atende os links de confirmação (`/confirmar/<token>`) até ser
+    /// interrompido.
+    ///
+    /// Abrir o link só mostra um botão; a confirmação é o `POST` dele, para
+    /// que antivírus e prévias que visitam os links sozinhos não confirmem por
+    /// ninguém. A página nunca mostra o sorteado
+    pub fn serve_run(conn: &mut Connection, ctx: &Config, addr: Option<String>) {
+        let addr = addr.unwrap_or(ctx.serve_addr.clone());
+        let server = match Server::http(&addr) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::error!("Não foi possível escutar em {addr}: {e}");
+                return;
+            }
+        };
+
+        match &ctx.url_publica {
+            Some(url) => tracing::info!("Escutando em {addr}, links de confirmação em {url}"),
+            None => tracing::warn!(
+                "Escutando em {addr}, mas sem PUBLIC_URL as mensagens não levam link de confirmação"
+            ),
+        }
+
+        for request in server.incoming_requests() {
+            let (status, corpo) = atender(conn, &request);
+
+            let response = Response::from_string(pagina(&corpo))
+                .with_status_code(status)
+                .with_header(
+                    Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap(),
+                );
+            if let Err(e) = request.respond(response) {
+                tracing::warn!("Falha ao responder: {e}");
+            }
+        }
+    }
+
+    /// O token de `/confirmar/<token>`, sem a query string que clientes de
+    /// email e rastreadores às vezes acrescentam ao link
+    fn token_da_url(url: &str) -> Option<&str> {
+        let caminho = url.split('?').next().unwrap_or_default();
+
+        caminho
+            .strip_prefix("/confirmar/")
+            .map(|t| t.trim_end_matches('/'))
+    }
+
+    fn atender(conn: &mut Connection, request: &Request) -> (u16, String) {
+        let Some(token) = token_da_url(request.url()) else {
+            return (404, "Página não encontrada.".to_owned());
+        };
+
+        let Some(envio) = crate::db::envios::get_envio_by_token(conn, token) else {
+            return (404, "Link de confirmação inválido.".to_owned());
+        };
+        let destino = crate::db::jogador::get_jogador_by_id(conn, envio.destino);
+
+        match request.method() {
+            Method::Get if envio.confirmado_em.is_some() => (
+                200,
+                format!(
+                    "{}, você já confirmou o recebimento. Obrigado!",
+                    escapar(&destino.nome)
+                ),
+            ),
+            Method::Get => (
+                200,
+                format!(
+                    "Olá, {}! Confirme que recebeu a mensagem do amigo secreto.\
+                    <form method=\"post\"><button>Confirmar</button></form>",
+                    escapar(&destino.nome)
+                ),
+            ),
+            Method::Post => {
+                if envio.confirmado_em.is_none() {
+                    crate::db::envios::confirmar(conn, token);
+                    tracing::info!("Envio {} confirmado por id {}", envio.id, destino.id);
+                }
+                (
+                    200,
+                    format!(
+                        "Obrigado, {}! Recebimento confirmado.",
+                        escapar(&destino.nome)
+                    ),
+                )
+            }
+            _ => (405, "Método não permitido.".to_owned()),
+        }
+    }
+
+    fn pagina(corpo: &str) -> String {
+        format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Amigo Secreto</title></head><body><p>{corpo}</p></body></html>"
+        )
+    }
+
+    fn escapar(texto: &str) -> String {
+        texto
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn token_sem_query_string() {
+            assert_eq!(token_da_url("/confirmar/abc"), Some("abc"));
+            assert_eq!(token_da_url("/confirmar/abc/"), Some("abc"));
+            assert_eq!(
+                token_da_url("/confirmar/abc?utm_source=email&utm_medium=link"),
+                Some("abc")
+            );
+            assert_eq!(token_da_url("/confirmar/abc/?x=1"), Some("abc"));
+            assert_eq!(token_da_url("/outra?/confirmar/abc"), None);
+        }
+    }
 }
@@ -0,0 +1,815 @@
+//! Arquivo portátil com um ou mais jogos e tudo o que se refere a eles, para
+//! levá-los a outra instalação ou juntar as bases de dois organizadores
+//! (`archive export` e `archive import`).
+//!
+//! O arquivo é um JSON assim:
+//!
+//! ```json
+//! {
+//!   "formato": "amigo-cli/arquivo",
+//!   "versao": 1,
+//!   "esquema": 11,
+//!   "exportado_em": "2026-10-19 12:00:00",
+//!   "tabelas": {
+//!     "jogos": [{"id": 1, "nome": "Família", "data_evento": "2026-12-24"}],
+//!     "jogadores": [{"id": 4, "nome": "Ana", "email": "ana@x.com", "jogo": 1, ...}],
+//!     "sorteios": [{"id": 2, "sal": "...", "verificador": {"base64": "..."}, ...}],
+//!     ...
+//!   }
+//! }
+//! ```
+//!
+//! `versao` é a deste formato e só muda se o envelope mudar; `esquema` é a
+//! última migração da base que exportou. As tabelas são `jogos`,
+//! `jogadores`, `exclusoes`, `sorteios`, `envios`, `outbox` e `revelacoes`,
+//! cada uma uma lista de linhas com as colunas como estão na base, e os BLOBs
+//! como `{"base64": "..."}`. Um arquivo de esquema mais antigo é importado
+//! normalmente (as colunas que ele não tem ficam com o padrão); de um mais
+//! novo é recusado.
+//!
+//! Na importação, todas as linhas ganham ids novos e as referências entre
+//! elas são traduzidas, então nada colide com o que já está na base. Os dados
+//! cifrados (sementes seladas, sorteados e mensagens na fila) são decifrados
+//! com a senha de quem exportou e cifrados de novo com a de quem importa.
+//!
+//! Os tokens de confirmação também são trocados, para que um link não valha
+//! nas duas bases nem colida ao importar o mesmo arquivo duas vezes. Os links
+//! já entregues passam a valer só na base de origem; nas mensagens ainda na
+//! fila, o link é reescrito com o token novo, ou, se a mensagem for cifrada
+//! com PGP ou assinada com DKIM, o item fica sem token. Do mesmo jeito, os
+//! `Message-ID` ganham uma parte aleatória nova, para que um bounce não case
+//! com um envio de outra importação; na fila, só uma mensagem assinada com
+//! DKIM fica sem ele.
+//!
+//! Sementes não seladas e tokens de confirmação vão em claro: o arquivo deve
+//! ser tratado como a própria base.
+
+use std::collections::{BTreeMap, HashMap};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rusqlite::{params_from_iter, types::Value as Sql, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{cripto::Chave, envio::aleatorio};
+
+pub const FORMATO: &str = "amigo-cli/arquivo";
+pub const VERSAO: u32 = 1;
+
+pub type Linha = Map<String, Value>;
+
+/// Tamanho dos tokens de confirmação gerados na importação, o mesmo do
+/// `enfileirar`
+const TAMANHO_TOKEN: usize = 32;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Arquivo {
+    pub formato: String,
+    pub versao: u32,
+    pub esquema: u32,
+    pub exportado_em: String,
+    pub tabelas: BTreeMap<String, Vec<Linha>>,
+}
+
+/// Uma tabela do arquivo e as colunas dela que apontam para outras
+struct Tabela {
+    nome: &'static str,
+    referencias: &'static [(&'static str, &'static str)],
+    /// Condição das linhas dos jogos exportados, com `{}` no lugar dos ids
+    filtro: &'static str,
+}
+
+/// Na ordem de importação, cada tabela depois das que ela referencia
+const TABELAS: &[Tabela] = &[
+    Tabela {
+        nome: "jogos",
+        referencias: &[],
+        filtro: "id IN ({})",
+    },
+    Tabela {
+        nome: "jogadores",
+        referencias: &[("jogo", "jogos")],
+        filtro: "jogo IN ({})",
+    },
+    Tabela {
+        nome: "exclusoes",
+        referencias: &[("jogador", "jogadores"), ("excluido", "jogadores")],
+        filtro: "jogador IN (SELECT id FROM jogadores WHERE jogo IN ({}))",
+    },
+    Tabela {
+        nome: "sorteios",
+        referencias: &[("jogo", "jogos")],
+        filtro: "jogo IN ({})",
+    },
+    Tabela {
+        nome: "envios",
+        referencias: &[
+            ("sorteio", "sorteios"),
+            ("destino", "jogadores"),
+            ("sorteado", "jogadores"),
+        ],
+        filtro: "sorteio IN (SELECT id FROM sorteios WHERE jogo IN ({}))",
+    },
+    Tabela {
+        nome: "outbox",
+        referencias: &[("sorteio", "sorteios"), ("destino", "jogadores")],
+        filtro: "sorteio IN (SELECT id FROM sorteios WHERE jogo IN ({}))",
+    },
+    Tabela {
+        nome: "revelacoes",
+        referencias: &[("envio", "envios"), ("sorteio", "sorteios")],
+        filtro: "sorteio IN (SELECT id FROM sorteios WHERE jogo IN ({}))",
+    },
+];
+
+pub fn exportar(conn: &Connection, jogos: &[u64]) -> Arquivo {
+    let ids = jogos
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let tabelas = TABELAS
+        .iter()
+        .map(|t| {
+            let sql = format!(
+                "SELECT * FROM {} WHERE {} ORDER BY rowid",
+                t.nome,
+                t.filtro.replace("{}", &ids)
+            );
+            let mut query = conn.prepare(&sql).unwrap();
+            let colunas: Vec<String> = query
+                .column_names()
+                .into_iter()
+                .map(str::to_owned)
+                .collect();
+
+            let linhas = query
+                .query_map([], |r| {
+                    Ok(colunas
+                        .iter()
+                        .enumerate()
+                        .map(|(i, c)| (c.clone(), para_json(r.get(i).unwrap())))
+                        .collect::<Linha>())
+                })
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+
+            (t.nome.to_owned(), linhas)
+        })
+        .collect();
+
+    Arquivo {
+        formato: FORMATO.to_owned(),
+        versao: VERSAO,
+        esquema: crate::db::backup::versao_embutida(),
+        exportado_em: chrono::Utc::now()
+            .format(crate::db::FORMATO_DATA)
+            .to_string(),
+        tabelas,
+    }
+}
+
+impl Arquivo {
+    /// Confere o envelope e as colunas de cada tabela contra a base
+    pub fn validar(&self, conn: &Connection) -> Result<(), String> {
+        if self.formato != FORMATO {
+            return Err(format!("formato `{}` desconhecido", self.formato));
+        }
+        if self.versao != VERSAO {
+            return Err(format!(
+                "versão {} do formato não suportada (esta é a {VERSAO})",
+                self.versao
+            ));
+        }
+
+        let esquema = crate::db::backup::versao_embutida();
+        if self.esquema > esquema {
+            return Err(format!(
+                "o arquivo é do esquema {}, mais novo que o deste programa ({esquema}); atualize o amigo-cli",
+                self.esquema
+            ));
+        }
+
+        for (nome, linhas) in &self.tabelas {
+            if !TABELAS.iter().any(|t| t.nome == nome) {
+                return Err(format!("tabela `{nome}` desconhecida"));
+            }
+
+            let colunas = colunas(conn, nome);
+            for linha in linhas {
+                if let Some(c) = linha.keys().find(|c| !colunas.contains(c)) {
+                    return Err(format!("coluna `{c}` desconhecida em `{nome}`"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sorteios que têm dados cifrados, que pedem as senhas para importar
+    pub fn cifrado(&self) -> bool {
+        self.linhas("sorteios")
+            .iter()
+            .any(|s| s.get("verificador").is_some_and(|v| !v.is_null()))
+    }
+
+    pub fn linhas(&self, tabela: &str) -> &[Linha] {
+        self.tabelas
+            .get(tabela)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+/// Grava o conteúdo do arquivo, já validado, com ids novos e os dados cifrados
+/// com `senha`. Devolve os ids novos dos jogos, pelos antigos.
+///
+/// A senha de quem exportou, se não vier em `senha_origem`, só é pedida no
+/// terminal se não for a mesma
+pub fn importar(
+    conn: &Connection,
+    arquivo: &Arquivo,
+    senha: Option<&str>,
+    mut senha_origem: Option<String>,
+) -> Result<BTreeMap<i64, i64>, String> {
+    let mut ids: HashMap<&str, HashMap<i64, i64>> = HashMap::new();
+    let mut chaves: HashMap<i64, (Chave, Chave)> = HashMap::new();
+    let mut sem_link = 0;
+    let mut sem_message_id = 0;
+
+    for tabela in TABELAS {
+        for linha in arquivo.linhas(tabela.nome) {
+            let mut linha = linha.clone();
+            let erro = |e: String| format!("{}: {e}", tabela.nome);
+
+            match tabela.nome {
+                "sorteios" => {
+                    if let Some((antiga, nova)) =
+                        recifrar_sorteio(&mut linha, senha, &mut senha_origem).map_err(erro)?
+                    {
+                        let id = inteiro(&linha, "id").map_err(erro)?.unwrap_or_default();
+                        chaves.insert(id, (antiga, nova));
+                    }
+                }
+                "envios" => {
+                    recifrar_envio(&mut linha, &chaves, &ids).map_err(erro)?;
+                    if linha.get("token").is_some_and(|t| !t.is_null()) {
+                        linha.insert("token".to_owned(), aleatorio(TAMANHO_TOKEN).into());
+                    }
+                    if let Some(id) = linha.get("message_id").and_then(Value::as_str) {
+                        let novo = novo_message_id(id);
+                        linha.insert(
+                            "message_id".to_owned(),
+                            novo.map_or(Value::Null, Value::from),
+                        );
+                    }
+                }
+                "outbox" => {
+                    let tinha = |linha: &Linha, c: &str| linha.get(c).is_some_and(|v| !v.is_null());
+                    let (token, message_id) = (tinha(&linha, "token"), tinha(&linha, "message_id"));
+                    recifrar_envio(&mut linha, &chaves, &ids).map_err(erro)?;
+                    if token && !tinha(&linha, "token") {
+                        sem_link += 1;
+                    }
+                    if message_id && !tinha(&linha, "message_id") {
+                        sem_message_id += 1;
+                    }
+                }
+                _ => (),
+            }
+
+            for (coluna, alvo) in tabela.referencias {
+                let Some(antigo) = inteiro(&linha, coluna).map_err(erro)? else {
+                    continue;
+                };
+                let novo = ids
+                    .get(alvo)
+                    .and_then(|m| m.get(&antigo))
+                    .ok_or(erro(format!("{coluna} {antigo} não está no arquivo")))?;
+                linha.insert(coluna.to_string(), (*novo).into());
+            }
+
+            let antigo = inteiro(&linha, "id").map_err(erro)?;
+            linha.remove("id");
+
+            let colunas = linha.keys().cloned().collect::<Vec<_>>();
+            let valores = linha
+                .values()
+                .map(para_sql)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(erro)?;
+            conn.execute(
+                &format!(
+                    "INSERT INTO {} ({}) VALUES ({})",
+                    tabela.nome,
+                    colunas.join(", "),
+                    vec!["?"; colunas.len()].join(", ")
+                ),
+                params_from_iter(valores),
+            )
+            .map_err(|e| erro(e.to_string()))?;
+
+            if let Some(antigo) = antigo {
+                ids.entry(tabela.nome)
+                    .or_default()
+                    .insert(antigo, conn.last_insert_rowid());
+            }
+        }
+    }
+
+    if sem_link > 0 {
+        tracing::warn!(
+            "{sem_link} mensagem(ns) na fila levam um link de confirmação que não pôde ser \
+             reescrito (PGP ou DKIM); nesta base elas não pedem confirmação"
+        );
+    }
+    if sem_message_id > 0 {
+        tracing::warn!(
+            "{sem_message_id} mensagem(ns) na fila são assinadas com DKIM e mantêm o \
+             Message-ID de origem; nesta base os bounces delas não são reconhecidos"
+        );
+    }
+
+    Ok(ids
+        .remove("jogos")
+        .unwrap_or_default()
+        .into_iter()
+        .collect())
+}
+
+/// Troca sal, verificador e semente selada do sorteio pelos da senha de
+/// destino; devolve as chaves antiga e nova, se ele tiver cifragem
+fn recifrar_sorteio(
+    linha: &mut Linha,
+    senha: Option<&str>,
+    senha_origem: &mut Option<String>,
+) -> Result<Option<(Chave, Chave)>, String> {
+    let (Some(sal), Some(verificador)) = (
+        linha.get("sal").and_then(Value::as_str).map(str::to_owned),
+        blob(linha, "verificador")?,
+    ) else {
+        return Ok(None);
+    };
+    let senha = senha.ok_or("falta a senha do organizador")?;
+
+    let antiga = match Chave::abrir(senha, &sal, &verificador) {
+        Some(c) => c,
+        None => {
+            let origem = senha_origem.get_or_insert_with(|| {
+                crate::prompt::ler_senha("Senha do organizador que exportou o arquivo: ")
+            });
+            Chave::abrir(origem, &sal, &verificador)
+                .ok_or("senha do organizador de origem incorreta")?
+        }
+    };
+
+    let sal = crate::cripto::create_sal();
+    let nova = Chave::derivar(senha, &sal);
+    linha.insert("sal".to_owned(), sal.into());
+    linha.insert("verificador".to_owned(), blob_json(nova.verificador()));
+
+    if let Some(semente) = blob(linha, "seed_cifrada")? {
+        let semente = antiga
+            .decifrar(&semente)
+            .ok_or("não foi possível decifrar a semente")?;
+        linha.insert("seed_cifrada".to_owned(), blob_json(nova.cifrar(&semente)));
+    }
+
+    Ok(Some((antiga, nova)))
+}
+
+/// Decifra o sorteado (e a mensagem, na fila) com a chave antiga do sorteio
+/// e cifra de novo com a nova, já com o id novo do sorteado
+fn recifrar_envio(
+    linha: &mut Linha,
+    chaves: &HashMap<i64, (Chave, Chave)>,
+    ids: &HashMap<&str, HashMap<i64, i64>>,
+) -> Result<(), String> {
+    let cifrados = (
+        blob(linha, "sorteado_cifrado")?,
+        blob(linha, "mensagem_cifrada")?,
+    );
+    if cifrados == (None, None) {
+        return Ok(());
+    }
+
+    let sorteio = inteiro(linha, "sorteio")?.unwrap_or_default();
+    let (antiga, nova) = chaves
+        .get(&sorteio)
+        .ok_or(format!("o sorteio {sorteio} não tem cifragem"))?;
+
+    if let Some(sorteado) = cifrados.0 {
+        let sorteado = antiga
+            .decifrar_sorteado(&sorteado)
+            .ok_or("não foi possível decifrar o sorteado")?;
+        let sorteado = ids
+            .get("jogadores")
+            .and_then(|m| m.get(&(sorteado as i64)))
+            .ok_or(format!("sorteado {sorteado} não está no arquivo"))?;
+        linha.insert(
+            "sorteado_cifrado".to_owned(),
+            blob_json(nova.cifrar_sorteado(*sorteado as u64)),
+        );
+    }
+
+    if let Some(mensagem) = cifrados.1 {
+        let mut mensagem = antiga
+            .decifrar(&mensagem)
+            .ok_or("não foi possível decifrar a mensagem")?;
+        if let Some(token) = linha.get("token").and_then(Value::as_str) {
+            let novo = trocar_token(&mut mensagem, token);
+            linha.insert("token".to_owned(), novo.map_or(Value::Null, Value::from));
+        }
+        if let Some(id) = linha.get("message_id").and_then(Value::as_str) {
+            let novo = trocar_message_id(&mut mensagem, id);
+            linha.insert(
+                "message_id".to_owned(),
+                novo.map_or(Value::Null, Value::from),
+            );
+        }
+        linha.insert(
+            "mensagem_cifrada".to_owned(),
+            blob_json(nova.cifrar(&mensagem)),
+        );
+    }
+
+    Ok(())
+}
+
+/// Troca o token do link de confirmação da mensagem por um novo, do mesmo
+/// tamanho, mesmo que o quoted-printable o tenha quebrado em duas linhas.
+/// Devolve `None`, sem mexer na mensagem, se o token não aparece nela (cifrada
+/// com PGP) ou se ela tem uma assinatura DKIM, que deixaria de valer
+fn trocar_token(mensagem: &mut [u8], token: &str) -> Option<String> {
+    if assinada_com_dkim(mensagem) {
+        return None;
+    }
+
+    // posições dos bytes da mensagem sem as quebras suaves (`=\r\n`)
+    let mut posicoes = vec![];
+    let mut i = 0;
+    while i < mensagem.len() {
+        if mensagem[i..].starts_with(b"=\r\n") {
+            i += 3;
+        } else {
+            posicoes.push(i);
+            i += 1;
+        }
+    }
+
+    let token = token.as_bytes();
+    let inicio = posicoes
+        .windows(token.len())
+        .position(|w| w.iter().map(|&p| mensagem[p]).eq(token.iter().copied()))?;
+
+    let novo = aleatorio(token.len());
+    for (p, b) in posicoes[inicio..inicio + token.len()]
+        .iter()
+        .zip(novo.bytes())
+    {
+        mensagem[*p] = b;
+    }
+
+    Some(novo)
+}
+
+/// O `Message-ID` com uma parte aleatória nova, do mesmo tamanho: em
+/// `<amigo.3.XzV53NlgTZbTrwJuCb2o@example.com>`, o trecho depois do último
+/// ponto antes do `@`
+fn novo_message_id(antigo: &str) -> Option<String> {
+    let (local, dominio) = antigo.split_once('@')?;
+    let (prefixo, parte) = local.rsplit_once('.')?;
+
+    Some(format!("{prefixo}.{}@{dominio}", aleatorio(parte.len())))
+}
+
+/// Troca o cabeçalho `Message-ID` da mensagem pelo de `novo_message_id`.
+/// Devolve `None`, sem mexer na mensagem, se ela tem uma assinatura DKIM, que
+/// cobre esse cabeçalho, ou se o id não aparece nela
+fn trocar_message_id(mensagem: &mut [u8], antigo: &str) -> Option<String> {
+    if assinada_com_dkim(mensagem) {
+        return None;
+    }
+
+    let novo = novo_message_id(antigo)?;
+    let posicao = mensagem
+        .windows(antigo.len())
+        .position(|w| w == antigo.as_bytes())?;
+    mensagem[posicao..posicao + antigo.len()].copy_from_slice(novo.as_bytes());
+
+    Some(novo)
+}
+
+fn assinada_com_dkim(mensagem: &[u8]) -> bool {
+    String::from_utf8_lossy(mensagem)
+        .lines()
+        .any(|l| l.to_ascii_lowercase().starts_with("dkim-signature:"))
+}
+
+fn colunas(conn: &Connection, tabela: &str) -> Vec<String> {
+    let mut query = conn
+        .prepare("SELECT name FROM pragma_table_info(?1)")
+        .unwrap();
+
+    query
+        .query_map([tabela], |r| r.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+fn inteiro(linha: &Linha, coluna: &str) -> Result<Option<i64>, String> {
+    match linha.get(coluna) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v
+            .as_i64()
+            .map(Some)
+            .ok_or(format!("`{coluna}` deveria ser um número, não {v}")),
+    }
+}
+
+fn blob(linha: &Linha, coluna: &str) -> Result<Option<Vec<u8>>, String> {
+    match linha.get(coluna).map(para_sql).transpose()? {
+        None | Some(Sql::Null) => Ok(None),
+        Some(Sql::Blob(b)) => Ok(Some(b)),
+        Some(_) => Err(format!("`{coluna}` deveria ser {{\"base64\": ...}}")),
+    }
+}
+
+fn blob_json(bytes: Vec<u8>) -> Value {
+    para_json(Sql::Blob(bytes))
+}
+
+fn para_json(valor: Sql) -> Value {
+    match valor {
+        Sql::Null => Value::Null,
+        Sql::Integer(i) => i.into(),
+        Sql::Real(f) => f.into(),
+        Sql::Text(t) => t.into(),
+        Sql::Blob(b) => serde_json::json!({ "base64": STANDARD.encode(b) }),
+    }
+}
+
+fn para_sql(valor: &Value) -> Result<Sql, String> {
+    Ok(match valor {
+        Value::Null => Sql::Null,
+        Value::Bool(b) => Sql::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Sql::Integer(i),
+            None => Sql::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => Sql::Text(s.clone()),
+        Value::Object(o) => match (o.len(), o.get("base64").and_then(Value::as_str)) {
+            (1, Some(b)) => Sql::Blob(STANDARD.decode(b).map_err(|e| e.to_string())?),
+            _ => return Err(format!("valor inválido: {valor}")),
+        },
+        Value::Array(_) => return Err(format!("valor inválido: {valor}")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::params;
+
+    use super::*;
+
+    const TOKEN_ENVIO: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+    const TOKEN_FILA: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+    const MESSAGE_ID_ENVIO: &str = "<amigo.5.AAAAAAAAAAAAAAAAAAAA@x.com>";
+    const MESSAGE_ID_FILA: &str = "<amigo.5.BBBBBBBBBBBBBBBBBBBB@x.com>";
+
+    fn base() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::migrar(&mut conn);
+        conn
+    }
+
+    /// Um jogo com três jogadores e um sorteio cifrado com `senha`: um envio
+    /// entregue (10 tirou 11) e uma mensagem na fila (11 tirou 12)
+    fn origem(senha: &str) -> Connection {
+        let conn = base();
+        let sal = crate::cripto::create_sal();
+        let chave = Chave::derivar(senha, &sal);
+
+        conn.execute("INSERT INTO jogos (id, nome) VALUES (1, 'Família')", [])
+            .unwrap();
+        for (id, nome) in [(10, "Ana"), (11, "Beto"), (12, "Caio")] {
+            conn.execute(
+                "INSERT INTO jogadores (id, nome, email, jogo) VALUES (?1, ?2, ?3, 1)",
+                params![id, nome, format!("{}@x.com", nome.to_lowercase())],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO sorteios (id, seed_cifrada, jogadores_hash, jogadores_qtd, jogo, sal, verificador)
+            VALUES (5, ?1, 'h', 3, 1, ?2, ?3)",
+            params![chave.cifrar(b"semente"), sal, chave.verificador()],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO envios (sorteio, destino, sorteado_cifrado, sucesso, token, message_id) VALUES (5, 10, ?1, 1, ?2, ?3)",
+            params![chave.cifrar_sorteado(11), TOKEN_ENVIO, MESSAGE_ID_ENVIO],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO outbox (sorteio, destino, sorteado_cifrado, remetente, destinatario, mensagem_cifrada, token, message_id)
+            VALUES (5, 11, ?1, 'org@x.com', 'beto@x.com', ?2, ?3, ?4)",
+            params![
+                chave.cifrar_sorteado(12),
+                chave.cifrar(format!("Message-ID: {MESSAGE_ID_FILA}\r\nSubject: Amigo Secreto\r\n\r\nConfirme: https://x/confirmar/{TOKEN_FILA}\r\n").as_bytes()),
+                TOKEN_FILA,
+                MESSAGE_ID_FILA
+            ],
+        )
+        .unwrap();
+
+        conn
+    }
+
+    /// Base de destino que já tem um jogo e um jogador, para que os ids novos
+    /// não coincidam com os do arquivo
+    fn destino() -> Connection {
+        let conn = base();
+        conn.execute("INSERT INTO jogos (nome) VALUES ('Outro')", [])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO jogadores (nome, email, jogo) VALUES ('Zé', 'ze@x.com', 1)",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn importado(conn: &Connection, jogo: i64) -> (Chave, i64, Vec<u8>, String, Vec<u8>, String) {
+        let (sorteio, sal, verificador) = conn
+            .query_row(
+                "SELECT id, sal, verificador FROM sorteios WHERE jogo = ?1",
+                [jogo],
+                |r| {
+                    Ok((
+                        r.get::<_, i64>(0)?,
+                        r.get::<_, String>(1)?,
+                        r.get::<_, Vec<u8>>(2)?,
+                    ))
+                },
+            )
+            .unwrap();
+        let chave = Chave::abrir("destino", &sal, &verificador).unwrap();
+        let (sorteado, token) = conn
+            .query_row(
+                "SELECT sorteado_cifrado, token FROM envios WHERE sorteio = ?1",
+                [sorteio],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        let (mensagem, token_fila) = conn
+            .query_row(
+                "SELECT mensagem_cifrada, token FROM outbox WHERE sorteio = ?1",
+                [sorteio],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+
+        (chave, sorteio, sorteado, token, mensagem, token_fila)
+    }
+
+    fn id_por_email(conn: &Connection, jogo: i64, email: &str) -> i64 {
+        conn.query_row(
+            "SELECT id FROM jogadores WHERE jogo = ?1 AND email = ?2",
+            params![jogo, email],
+            |r| r.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn importa_com_ids_novos_e_outra_senha() {
+        let arquivo = exportar(&origem("origem"), &[1]);
+        let conn = destino();
+
+        let jogos = importar(&conn, &arquivo, Some("destino"), Some("origem".to_owned())).unwrap();
+        assert_eq!(jogos, BTreeMap::from([(1, 2)]));
+
+        let (chave, sorteio, sorteado, token, mensagem, token_fila) = importado(&conn, 2);
+        assert_ne!(sorteio, 5);
+
+        // o sorteado decifra com a senha nova e aponta para o id novo do Beto
+        let beto = id_por_email(&conn, 2, "beto@x.com");
+        assert_ne!(beto, 11);
+        assert_eq!(chave.decifrar_sorteado(&sorteado), Some(beto as u64));
+
+        let semente: Vec<u8> = conn
+            .query_row(
+                "SELECT seed_cifrada FROM sorteios WHERE id = ?1",
+                [sorteio],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(chave.decifrar(&semente).unwrap(), b"semente");
+
+        // tokens novos, e o link da mensagem na fila acompanha o token dela
+        assert_ne!(token, TOKEN_ENVIO);
+        assert_ne!(token_fila, TOKEN_FILA);
+        let mensagem = String::from_utf8(chave.decifrar(&mensagem).unwrap()).unwrap();
+        assert!(mensagem.contains(&format!("/confirmar/{token_fila}")));
+
+        let destino: i64 = conn
+            .query_row(
+                "SELECT destino FROM outbox WHERE sorteio = ?1",
+                [sorteio],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(destino, beto);
+    }
+
+    #[test]
+    fn importa_o_mesmo_arquivo_duas_vezes() {
+        let arquivo = exportar(&origem("origem"), &[1]);
+        let conn = destino();
+
+        importar(&conn, &arquivo, Some("destino"), Some("origem".to_owned())).unwrap();
+        let jogos = importar(&conn, &arquivo, Some("destino"), Some("origem".to_owned())).unwrap();
+        assert_eq!(jogos, BTreeMap::from([(1, 3)]));
+
+        let (_, _, _, primeiro, _, _) = importado(&conn, 2);
+        let (_, _, _, segundo, _, _) = importado(&conn, 3);
+        assert_ne!(primeiro, segundo);
+    }
+
+    #[test]
+    fn message_ids_novos() {
+        let arquivo = exportar(&origem("origem"), &[1]);
+        let conn = destino();
+
+        let mut vistos = vec![MESSAGE_ID_ENVIO.to_owned(), MESSAGE_ID_FILA.to_owned()];
+        for jogo in [2, 3] {
+            importar(&conn, &arquivo, Some("destino"), Some("origem".to_owned())).unwrap();
+            let (chave, sorteio, ..) = importado(&conn, jogo);
+
+            let envio: String = conn
+                .query_row(
+                    "SELECT message_id FROM envios WHERE sorteio = ?1",
+                    [sorteio],
+                    |r| r.get(0),
+                )
+                .unwrap();
+            let (fila, mensagem): (String, Vec<u8>) = conn
+                .query_row(
+                    "SELECT message_id, mensagem_cifrada FROM outbox WHERE sorteio = ?1",
+                    [sorteio],
+                    |r| Ok((r.get(0)?, r.get(1)?)),
+                )
+                .unwrap();
+
+            for id in [&envio, &fila] {
+                assert!(id.starts_with("<amigo.5.") && id.ends_with("@x.com>"));
+                assert!(!vistos.contains(id));
+                vistos.push(id.clone());
+            }
+
+            let mensagem = String::from_utf8(chave.decifrar(&mensagem).unwrap()).unwrap();
+            assert!(mensagem.starts_with(&format!("Message-ID: {fila}\r\n")));
+        }
+    }
+
+    #[test]
+    fn senha_de_origem_errada() {
+        let arquivo = exportar(&origem("origem"), &[1]);
+        let conn = destino();
+
+        let erro = importar(&conn, &arquivo, Some("destino"), Some("outra".to_owned()));
+        assert!(erro.is_err());
+    }
+
+    #[test]
+    fn token_quebrado_pelo_quoted_printable() {
+        let mut mensagem = format!(
+            "Confirme: https://x/confirmar/{}=\r\n{}\r\n",
+            &TOKEN_FILA[..10],
+            &TOKEN_FILA[10..]
+        )
+        .into_bytes();
+
+        let novo = trocar_token(&mut mensagem, TOKEN_FILA).unwrap();
+        let esperado = format!(
+            "Confirme: https://x/confirmar/{}=\r\n{}\r\n",
+            &novo[..10],
+            &novo[10..]
+        );
+        assert_eq!(String::from_utf8(mensagem).unwrap(), esperado);
+    }
+
+    #[test]
+    fn token_com_dkim_nao_e_trocado() {
+        let mut mensagem =
+            format!("DKIM-Signature: v=1\r\n\r\n/confirmar/{TOKEN_FILA}").into_bytes();
+        assert_eq!(trocar_token(&mut mensagem, TOKEN_FILA), None);
+
+        let mut mensagem = b"-----BEGIN PGP MESSAGE-----".to_vec();
+        assert_eq!(trocar_token(&mut mensagem, TOKEN_FILA), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! Leitura de mensagens devolvidas (bounces) de um Maildir ou mbox local.
+//!
+//! Um bounce padrão (DSN, RFC 3464) tem uma parte `message/delivery-status`
+//! com o resultado por destinatário e devolve a mensagem original, ou só os
+//! cabeçalhos dela, em `message/rfc822` ou `text/rfc822-headers`. É pelo
+//! `Message-ID` original que o envio é encontrado. Bounces sem DSN só são
+//! aceitos se vierem de `MAILER-DAEMON` ou `postmaster` e citarem um
+//! `Message-ID` em algum lugar do texto.
+
+use std::{io::BufReader, path::Path};
+
+use mail_parser::{MessageParser, MimeHeaders, PartType};
+
+/// Um bounce já interpretado
+pub struct Bounce {
+    /// `Message-ID` da mensagem devolvida, entre `<` e `>`
+    pub message_id: String,
+    pub motivo: String,
+}
+
+/// Conteúdo de todas as mensagens em `cur/` e `new/` do Maildir
+pub fn ler_maildir(path: &Path) -> Result<Vec<Vec<u8>>, String> {
+    mail_parser::mailbox::maildir::MessageIterator::new(path)
+        .map_err(|e| e.to_string())?
+        .map(|m| m.map(|m| m.contents().to_vec()).map_err(|e| e.to_string()))
+        .collect()
+}
+
+pub fn ler_mbox(path: &Path) -> Result<Vec<Vec<u8>>, String> {
+    let arquivo = std::fs::File::open(path).map_err(|e| e.to_string())?;
+
+    mail_parser::mailbox::mbox::MessageIterator::new(BufReader::new(arquivo))
+        .map(|m| m.map(|m| m.unwrap_contents()).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Interpreta uma mensagem como bounce; `None` se ela não for um, ou se o DSN
+/// disser que a entrega não falhou (atrasos, por exemplo)
+pub fn analisar(mensagem: &[u8]) -> Option<Bounce> {
+    let mensagem = MessageParser::default().parse(mensagem)?;
+
+    let mut status = None;
+    let mut original = None;
+
+    for parte in &mensagem.parts {
+        let Some(tipo) = parte.content_type() else {
+            continue;
+        };
+
+        match (tipo.ctype(), tipo.subtype().unwrap_or_default()) {
+            ("message", "delivery-status") => {
+                status = Some(String::from_utf8_lossy(parte.contents()).into_owned())
+            }
+            ("message", "rfc822" | "global") => {
+                if let PartType::Message(m) = &parte.body {
+                    original = original.or(m.message_id().map(str::to_owned));
+                }
+            }
+            ("text", "rfc822-headers") => {
+                original = original.or(MessageParser::default()
+                    .parse_headers(parte.contents())
+                    .and_then(|m| m.message_id().map(str::to_owned)));
+            }
+            _ => (),
+        }
+    }
+
+    let motivo = match status {
+        Some(status) => {
+            let campos = campos_dsn(&status);
+            let campo = |nome: &str| {
+                campos
+                    .iter()
+                    .find(|(c, _)| c.eq_ignore_ascii_case(nome))
+                    .map(|(_, v)| v.as_str())
+            };
+
+            if !campo("Action").is_some_and(|a| a.eq_ignore_ascii_case("failed")) {
+                return None;
+            }
+
+            match (campo("Status"), campo("Diagnostic-Code")) {
+                (Some(s), Some(d)) => format!("bounce {s}: {d}"),
+                (Some(s), None) => format!("bounce {s}"),
+                (None, Some(d)) => format!("bounce: {d}"),
+                (None, None) => "bounce sem motivo informado".to_owned(),
+            }
+        }
+        None => {
+            // sem DSN, só confia em mensagens do próprio servidor de e-mail
+            let remetente = mensagem
+                .from()
+                .and_then(|f| f.first())
+                .and_then(|a| a.address())
+                .and_then(|a| a.split_once('@'))
+                .map(|(local, _)| local.to_ascii_lowercase());
+            if !matches!(remetente.as_deref(), Some("mailer-daemon" | "postmaster")) {
+                return None;
+            }
+
+            format!("bounce: {}", mensagem.subject().unwrap_or("sem assunto"))
+        }
+    };
+
+    let original = original.or_else(|| {
+        // bounces sem DSN: procura um Message-ID citado no texto, que não seja
+        // o do próprio bounce
+        let texto = String::from_utf8_lossy(mensagem.raw_message());
+        let proprio = mensagem.message_id().unwrap_or_default();
+
+        texto
+            .lines()
+            .filter_map(|l| {
+                let (nome, valor) = l.split_once(':')?;
+                nome.trim()
+                    .eq_ignore_ascii_case("Message-ID")
+                    .then(|| valor.trim().trim_matches(['<', '>']).to_owned())
+            })
+            .find(|id| id != proprio)
+    })?;
+
+    Some(Bounce {
+        message_id: format!("<{}>", original.trim_matches(['<', '>'])),
+        motivo,
+    })
+}
+
+/// Campos de todos os blocos de um `message/delivery-status`, desdobrando as
+/// linhas de continuação
+fn campos_dsn(status: &str) -> Vec<(String, String)> {
+    let mut campos: Vec<(String, String)> = vec![];
+
+    for linha in status.lines() {
+        if linha.starts_with([' ', '\t']) {
+            if let Some((_, valor)) = campos.last_mut() {
+                valor.push(' ');
+                valor.push_str(linha.trim());
+            }
+        } else if let Some((nome, valor)) = linha.split_once(':') {
+            campos.push((nome.trim().to_owned(), valor.trim().to_owned()));
+        }
+    }
+
+    campos
+}
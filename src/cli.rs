@@ -20,6 +20,33 @@ pub enum Commands {
         #[command(subcommand)]
         action: EnvioAction,
     },
+    Smtp {
+        #[command(subcommand)]
+        action: SmtpAction,
+    },
+    /// Cópias de segurança da base de dados inteira
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// Arquivo portátil de jogos, para levá-los a outra base
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveAction,
+    },
+    /// Entrega as mensagens que estão na fila, até ela esvaziar
+    Worker {
+        /// Continua rodando, enviando também as mensagens agendadas quando
+        /// chegar a hora delas
+        #[arg(long)]
+        daemon: bool,
+    },
+    /// Serve os links de confirmação das mensagens (`PUBLIC_URL`)
+    Serve {
+        /// Endereço onde escutar; o padrão vem de `SERVE_ADDR`
+        #[arg(long)]
+        addr: Option<String>,
+    },
 }
 
 #[derive(Clone, Subcommand, Debug)]
@@ -35,15 +62,77 @@ pub enum JogoAction {
         #[arg(short, long, default_value = "csv")]
         format: JogoFromFormat,
 
+        /// Arquivo a importar; `-` lê da entrada padrão
         #[arg(short, long)]
         path: PathBuf,
 
-        #[arg(short, long)]
-        nome: String,
+        /// Nome do jogo novo; em JSON e YAML, pode vir do próprio arquivo
+        #[arg(short, long, conflicts_with = "into")]
+        nome: Option<String>,
+
+        /// Acrescenta os jogadores a este jogo em vez de criar outro; quem já
+        /// está nele (pelo email) só tem o nome atualizado
+        #[arg(long)]
+        into: Option<u64>,
+
+        /// Coluna de um campo, pelo título ou número: `nome=<coluna>` ou
+        /// `email=<coluna>`
+        #[arg(long = "map", value_name = "CAMPO=COLUNA")]
+        colunas: Vec<String>,
+
+        /// O arquivo não tem linha de títulos
+        #[arg(long)]
+        no_header: bool,
+
+        /// Qual email usar dos contatos vCard que têm mais de um
+        #[arg(long, value_enum, default_value_t)]
+        email: EscolhaEmail,
+
+        /// Aba da planilha, pelo nome ou pelo número; sem ela, a primeira
+        #[arg(long)]
+        sheet: Option<String>,
+
+        /// Valida e mostra o que seria importado, sem gravar nada
+        #[arg(long)]
+        dry_run: bool,
     },
     Inspect {
         id: u64,
     },
+    Set {
+        id: u64,
+
+        #[command(subcommand)]
+        param: JogoSetParams,
+    },
+    /// Exporta o jogo com jogadores, sorteios e situação dos envios; o
+    /// arquivo pode voltar por `jogo from`
+    Export {
+        id: u64,
+
+        #[arg(short, long, default_value = "json")]
+        format: JogoExportFormat,
+
+        /// Arquivo de saída; `-` escreve na saída padrão
+        #[arg(short, long)]
+        path: PathBuf,
+
+        /// Inclui quem tirou quem (pede confirmação e fica registrado)
+        #[arg(long)]
+        reveal: bool,
+    },
+}
+
+#[derive(Clone, Subcommand, Debug)]
+pub enum JogoSetParams {
+    Nome {
+        val: String,
+    },
+    /// Dia da troca de presentes (`AAAA-MM-DD`), usado pelos lembretes; sem
+    /// data, remove
+    DataEvento {
+        val: Option<String>,
+    },
 }
 
 #[derive(Clone, Subcommand, Debug)]
@@ -75,22 +164,63 @@ pub enum JogadoresAction {
 
 #[derive(Clone, Subcommand, Debug)]
 pub enum JogadoresSetParams {
-    Nome { val: String },
-    Email { val: String },
+    Nome {
+        val: String,
+    },
+    Email {
+        val: String,
+    },
+    /// Lê a chave pública de um arquivo ASCII armor; sem arquivo, remove a chave
+    PgpKey {
+        path: Option<PathBuf>,
+    },
 }
 
 #[derive(Debug, Clone, clap::ValueEnum)]
 pub enum JogoFromFormat {
     Csv,
+    /// O jogo inteiro, com grupos, exclusões, idiomas e listas de desejos
+    Json,
+    Yaml,
+    /// Contatos vCard (2.1, 3 ou 4), como os exportados pelo celular
+    Vcf,
+    Xlsx,
+    Ods,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum JogoExportFormat {
+    Csv,
+    Json,
+}
+
+/// Qual email usar de um contato que tem mais de um
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum EscolhaEmail {
+    /// O marcado como preferido; sem marcação, o primeiro
+    #[default]
+    Preferido,
+    Primeiro,
+    /// Pergunta no terminal
+    Perguntar,
 }
 
 #[derive(Clone, Subcommand, Debug)]
 pub enum SorteioAction {
     New {
         jogo: u64,
+
+        /// Guarda a semente cifrada com a senha do organizador
+        #[arg(long)]
+        selar: bool,
     },
     Run {
         sorteio: u64,
+
+        /// Agenda as mensagens para este horário local (`AAAA-MM-DD HH:MM`);
+        /// quem envia é o `worker --daemon`
+        #[arg(long)]
+        at: Option<String>,
     },
     Ls {
         #[arg(short, long, default_value=None)]
@@ -99,6 +229,27 @@ pub enum SorteioAction {
     Inspect {
         sorteio: u64,
     },
+    /// Cifra a semente de um sorteio já existente
+    Seal {
+        sorteio: u64,
+    },
+    /// Apaga a semente, guardando apenas o seu hash
+    DiscardSeed {
+        sorteio: u64,
+    },
+    /// Confere uma semente contra o hash guardado no sorteio
+    Verify {
+        sorteio: u64,
+        seed: String,
+    },
+    /// Manda a todos os jogadores a corrente completa, depois da data do evento
+    Reveal {
+        sorteio: u64,
+
+        /// Revela mesmo antes da data do evento, ou de novo
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Clone, Subcommand, Debug)]
@@ -106,13 +257,72 @@ pub enum EnvioAction {
     Ls {
         #[arg(short, long, default_value=None)]
         sorteio: Option<u64>,
+
+        /// Mostra os sorteados (pede confirmação e fica registrado)
+        #[arg(long)]
+        reveal: bool,
     },
     Inspect {
         envio: u64,
+
+        /// Mostra o sorteado (pede confirmação e fica registrado)
+        #[arg(long)]
+        reveal: bool,
     },
     Redo {
         envio: u64,
     },
+    /// Lê os bounces de uma caixa local e marca os envios devolvidos como falhos
+    Bounces {
+        #[arg(long, required_unless_present = "mbox", conflicts_with = "mbox")]
+        maildir: Option<PathBuf>,
+
+        #[arg(long)]
+        mbox: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Subcommand, Debug)]
+pub enum DbAction {
+    /// Copia a base para um arquivo novo, mesmo com o worker rodando
+    Backup { path: PathBuf },
+    /// Substitui a base pelo conteúdo de uma cópia feita com `db backup`
+    Restore {
+        path: PathBuf,
+
+        /// Não pede confirmação
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Clone, Subcommand, Debug)]
+pub enum ArchiveAction {
+    /// Exporta os jogos (todos, se nenhum for indicado) com jogadores,
+    /// sorteios, envios e fila
+    Export {
+        jogos: Vec<u64>,
+
+        /// Arquivo de saída; `-` escreve na saída padrão
+        #[arg(short, long)]
+        path: PathBuf,
+    },
+    /// Importa os jogos de um arquivo com ids novos, sem tocar nos que já
+    /// estão na base
+    Import {
+        /// `-` lê da entrada padrão
+        path: PathBuf,
+    },
+}
+
+#[derive(Clone, Subcommand, Debug)]
+pub enum SmtpAction {
+    /// Confere a configuração de envio sem tocar na base de dados
+    Test {
+        /// Envia uma mensagem de teste; sem endereço, vai para o próprio remetente
+        #[arg(long, num_args = 0..=1)]
+        to: Option<Option<String>>,
+    },
 }
 
 #[derive(Debug, Parser)]
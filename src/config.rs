@@ -1,4 +1,6 @@
-use crate::envio::ProcessoEnvio;
+use chrono::NaiveDate;
+
+use crate::{db::Jogador, dkim::Dkim, envio::ProcessoEnvio};
 
 pub struct Config {
     pub smtp_sender: String,
@@ -6,10 +8,47 @@ pub struct Config {
     pub smtp_port: u16,
     pub smtp_username: String,
     pub smtp_password: String,
+    /// Limite de mensagens por minuto; sem limite se ausente
+    pub smtp_messages_per_minute: Option<u32>,
+    pub smtp_max_connections: u32,
+    /// Tentativas extras após um erro temporário (4xx) do servidor
+    pub smtp_max_retries: u32,
+    /// Espera antes da primeira nova tentativa; dobra a cada tentativa
+    pub smtp_backoff: std::time::Duration,
+    /// Quantas passagens do `worker` um item da `outbox` aguenta antes de
+    /// virar um envio com erro
+    pub outbox_max_attempts: u32,
     pub format_message: fn(ProcessoEnvio) -> String,
     pub subject: String,
+    pub format_lembrete: fn(ProcessoEnvio, NaiveDate) -> String,
+    pub subject_lembrete: String,
+    pub format_revelacao: fn(ProcessoEnvio, Vec<(Jogador, Jogador)>) -> String,
+    pub subject_revelacao: String,
+    /// Quantos dias antes da data do evento os lembretes começam a sair
+    pub lembrete_dias: u32,
 
     pub db_path: String,
+
+    /// Quando ligado (padrão), as listagens nunca mostram quem tirou quem
+    /// sem um `--reveal` explícito
+    pub modo_cego: bool,
+
+    /// Senha usada para cifrar os sorteados; pedida no terminal se ausente
+    pub senha_organizador: Option<String>,
+
+    /// Quem recebe o resumo de cada rodada de envios; sem ele, só há os logs
+    pub organizador_email: Option<String>,
+    /// Manda também ao organizador um recibo por mensagem entregue
+    pub organizador_recibos: bool,
+
+    /// Endereço público do `serve`, base dos links de confirmação; sem ele,
+    /// as mensagens não pedem confirmação
+    pub url_publica: Option<String>,
+    /// Onde o `serve` escuta
+    pub serve_addr: String,
+
+    /// Assinatura DKIM das mensagens, se `DKIM_SELECTOR` estiver definido
+    pub dkim: Option<Dkim>,
 }
 
 impl Config {
@@ -25,14 +64,147 @@ impl Config {
                 .unwrap(),
             smtp_username: std::env::var("SMTP_USER").unwrap(),
             smtp_password: std::env::var("SMTP_PASSWORD").unwrap(),
+            smtp_messages_per_minute: std::env::var("SMTP_MESSAGES_PER_MINUTE").ok().map(
+                |x| match x.parse() {
+                    Ok(0) | Err(_) => {
+                        panic!("SMTP_MESSAGES_PER_MINUTE deve ser um número maior que zero: {x}")
+                    }
+                    Ok(m) => m,
+                },
+            ),
+            smtp_max_connections: std::env::var("SMTP_MAX_CONNECTIONS")
+                .unwrap_or("1".to_owned())
+                .parse()
+                .unwrap(),
+            smtp_max_retries: std::env::var("SMTP_MAX_RETRIES")
+                .unwrap_or("3".to_owned())
+                .parse()
+                .unwrap(),
+            smtp_backoff: std::time::Duration::from_millis(
+                std::env::var("SMTP_BACKOFF_MS")
+                    .unwrap_or("2000".to_owned())
+                    .parse()
+                    .unwrap(),
+            ),
+            outbox_max_attempts: std::env::var("OUTBOX_MAX_ATTEMPTS")
+                .unwrap_or("10".to_owned())
+                .parse()
+                .unwrap(),
             format_message: |e| {
-                return format!(
-                    "{}, seu amigo secreto foi sorteado! É {}",
-                    e.destino.nome, e.sorteado.nome
-                );
+                if em_ingles(&e.destino) {
+                    format!(
+                        "{}, your secret santa has been drawn! It's {}{}",
+                        e.destino.nome,
+                        e.sorteado.nome,
+                        lista_desejos(&e.destino, &e.sorteado)
+                    )
+                } else {
+                    format!(
+                        "{}, seu amigo secreto foi sorteado! É {}{}",
+                        e.destino.nome,
+                        e.sorteado.nome,
+                        lista_desejos(&e.destino, &e.sorteado)
+                    )
+                }
             },
             subject: "Amigo Secreto".to_owned(),
+            format_lembrete: |e, data| {
+                if em_ingles(&e.destino) {
+                    format!(
+                        "{}, reminder: the secret santa gift exchange is on {}, and you drew {}!{}",
+                        e.destino.nome,
+                        data.format("%Y-%m-%d"),
+                        e.sorteado.nome,
+                        lista_desejos(&e.destino, &e.sorteado)
+                    )
+                } else {
+                    format!(
+                        "{}, lembrete: a troca de presentes do amigo secreto é dia {}, e você tirou {}!{}",
+                        e.destino.nome,
+                        data.format("%d/%m/%Y"),
+                        e.sorteado.nome,
+                        lista_desejos(&e.destino, &e.sorteado)
+                    )
+                }
+            },
+            subject_lembrete: "Lembrete: Amigo Secreto".to_owned(),
+            format_revelacao: |e, pares| {
+                let ingles = em_ingles(&e.destino);
+                let corrente = pares
+                    .iter()
+                    .map(|(destino, sorteado)| {
+                        if ingles {
+                            format!("{} drew {}", destino.nome, sorteado.nome)
+                        } else {
+                            format!("{} tirou {}", destino.nome, sorteado.nome)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                if ingles {
+                    format!(
+                        "{}, the secret santa is over! This was the whole chain:\n\n{}",
+                        e.destino.nome, corrente
+                    )
+                } else {
+                    format!(
+                        "{}, o amigo secreto acabou! Esta foi a corrente completa:\n\n{}",
+                        e.destino.nome, corrente
+                    )
+                }
+            },
+            subject_revelacao: "Amigo Secreto: quem tirou quem".to_owned(),
+            lembrete_dias: std::env::var("REMINDER_DAYS_BEFORE")
+                .unwrap_or("7".to_owned())
+                .parse()
+                .unwrap(),
             db_path: std::env::var("DB_PATH").unwrap_or("sqlite.db".to_string()),
+            modo_cego: std::env::var("BLIND_MODE")
+                .unwrap_or("true".to_owned())
+                .parse()
+                .unwrap(),
+            senha_organizador: std::env::var("ORGANIZER_PASSPHRASE").ok(),
+            organizador_email: std::env::var("ORGANIZER_EMAIL").ok(),
+            organizador_recibos: std::env::var("ORGANIZER_RECEIPTS")
+                .unwrap_or("false".to_owned())
+                .parse()
+                .unwrap(),
+            url_publica: std::env::var("PUBLIC_URL").ok(),
+            serve_addr: std::env::var("SERVE_ADDR").unwrap_or("127.0.0.1:8080".to_owned()),
+            dkim: Dkim::from_env(),
         }
     }
 }
+
+/// Se as mensagens para o jogador saem em inglês (`idioma` começando com
+/// `en`); sem idioma, ou com qualquer outro, saem em português
+fn em_ingles(jogador: &Jogador) -> bool {
+    jogador
+        .idioma
+        .as_deref()
+        .is_some_and(|idioma| idioma.to_lowercase().starts_with("en"))
+}
+
+/// A lista de desejos do sorteado, no idioma do destino, para o fim das
+/// mensagens; vazio se não houver
+fn lista_desejos(destino: &Jogador, sorteado: &Jogador) -> String {
+    let titulo = if em_ingles(destino) {
+        format!("{}'s wishlist", sorteado.nome)
+    } else {
+        format!("Lista de desejos de {}", sorteado.nome)
+    };
+
+    match &sorteado.lista_desejos {
+        Some(lista) => format!(
+            "\n\n{}:\n{}",
+            titulo,
+            lista
+                .lines()
+                .map(|item| format!("- {item}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,228 @@
+//! Cifragem das atribuições guardadas na base de dados.
+//!
+//! Cada sorteio tem um sal próprio; a chave é derivada dele e da senha do
+//! organizador com Argon2id, e o sorteado de cada envio é guardado cifrado
+//! com ChaCha20-Poly1305. O `verificador` do sorteio permite detectar uma
+//! senha errada antes de cifrar qualquer coisa com ela.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::{distributions::Alphanumeric, Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config,
+    db::{Envio, Sorteio},
+};
+
+const TAMANHO_NONCE: usize = 12;
+const CONTEUDO_VERIFICADOR: &[u8] = b"amigo-cli";
+
+#[derive(Clone)]
+pub struct Chave(Key);
+
+impl Chave {
+    pub fn derivar(senha: &str, sal: &str) -> Chave {
+        let mut chave = Key::default();
+
+        argon2::Argon2::default()
+            .hash_password_into(senha.as_bytes(), sal.as_bytes(), &mut chave)
+            .unwrap();
+
+        Chave(chave)
+    }
+
+    /// Chave de um sorteio já cifrado; `None` se a senha não for a dele
+    pub fn abrir(senha: &str, sal: &str, verificador: &[u8]) -> Option<Chave> {
+        let chave = Chave::derivar(senha, sal);
+        (chave.decifrar(verificador).as_deref() == Some(CONTEUDO_VERIFICADOR)).then_some(chave)
+    }
+
+    /// O que guardar em `sorteios.verificador` para esta chave
+    pub fn verificador(&self) -> Vec<u8> {
+        self.cifrar(CONTEUDO_VERIFICADOR)
+    }
+
+    pub fn cifrar(&self, dados: &[u8]) -> Vec<u8> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut saida = nonce.to_vec();
+
+        saida.extend(
+            ChaCha20Poly1305::new(&self.0)
+                .encrypt(&nonce, dados)
+                .unwrap(),
+        );
+        saida
+    }
+
+    /// Retorna `None` se a chave estiver errada ou os dados adulterados
+    pub fn decifrar(&self, dados: &[u8]) -> Option<Vec<u8>> {
+        if dados.len() < TAMANHO_NONCE {
+            return None;
+        }
+
+        let (nonce, cifrado) = dados.split_at(TAMANHO_NONCE);
+        ChaCha20Poly1305::new(&self.0)
+            .decrypt(Nonce::from_slice(nonce), cifrado)
+            .ok()
+    }
+
+    pub fn cifrar_sorteado(&self, sorteado: u64) -> Vec<u8> {
+        self.cifrar(&sorteado.to_le_bytes())
+    }
+
+    pub fn decifrar_sorteado(&self, dados: &[u8]) -> Option<u64> {
+        let bytes = self.decifrar(dados)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+pub fn create_sal() -> String {
+    ChaCha20Rng::from_entropy()
+        .sample_iter(Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect::<String>()
+}
+
+/// SHA-256 da semente, guardado para que uma semente descartada ainda possa
+/// ser conferida depois com `sorteio verify`
+pub fn compromisso(seed: &str) -> String {
+    format!("{:x}", Sha256::digest(seed.as_bytes()))
+}
+
+/// Guarda a senha do organizador e as chaves já derivadas, para que cada
+/// comando peça a senha no máximo uma vez
+pub struct Chaveiro {
+    senha: String,
+    chaves: HashMap<u64, Chave>,
+    /// Sorteios que a senha não abre, para não derivar a chave de novo a cada
+    /// passagem do `worker`
+    recusados: BTreeSet<u64>,
+}
+
+impl Chaveiro {
+    pub fn new(ctx: &Config) -> Chaveiro {
+        let senha = match &ctx.senha_organizador {
+            Some(s) => s.clone(),
+            None => crate::prompt::ler_senha("Senha do organizador: "),
+        };
+
+        Chaveiro::com_senha(senha)
+    }
+
+    /// Chaveiro de uma senha já conhecida, sem consultar a configuração
+    pub fn com_senha(senha: String) -> Chaveiro {
+        Chaveiro {
+            senha,
+            chaves: HashMap::new(),
+            recusados: BTreeSet::new(),
+        }
+    }
+
+    pub fn senha(&self) -> &str {
+        &self.senha
+    }
+
+    /// Se a senha abre o sorteio; um sorteio ainda sem verificador aceita
+    /// qualquer senha. Serve para pular, em vez de entrar em pânico, sorteios
+    /// cifrados com outra senha
+    pub fn abre(&mut self, conn: &mut Connection, sorteio: u64) -> bool {
+        if self.chaves.contains_key(&sorteio) {
+            return true;
+        }
+        if self.recusados.contains(&sorteio) {
+            return false;
+        }
+
+        let abre = match crate::db::sorteio::get_cifragem(conn, sorteio) {
+            (Some(sal), Some(verificador)) => {
+                Chave::abrir(&self.senha, &sal, &verificador).is_some()
+            }
+            _ => true,
+        };
+        if !abre {
+            self.recusados.insert(sorteio);
+        }
+        abre
+    }
+
+    /// Sorteios que `abre` já recusou
+    pub fn recusados(&self) -> &BTreeSet<u64> {
+        &self.recusados
+    }
+
+    /// Chave do sorteio, criando sal e verificador na primeira vez. Envios de
+    /// antes da cifragem têm o sorteado cifrado assim que a chave é aberta.
+    ///
+    /// Entra em pânico se a senha não for a mesma usada antes no sorteio
+    pub fn chave(&mut self, conn: &mut Connection, sorteio: u64) -> Chave {
+        if let Some(c) = self.chaves.get(&sorteio) {
+            return c.clone();
+        }
+
+        let (sal, verificador) = crate::db::sorteio::get_cifragem(conn, sorteio);
+        let sal = sal.unwrap_or_else(create_sal);
+        let chave = Chave::derivar(&self.senha, &sal);
+
+        match verificador {
+            Some(v) => {
+                if Chave::abrir(&self.senha, &sal, &v).is_none() {
+                    panic!("Senha incorreta para o sorteio {sorteio}");
+                }
+            }
+            None => crate::db::sorteio::update_cifragem(conn, sorteio, &sal, chave.verificador()),
+        }
+
+        let cifrados = crate::db::envios::cifrar_sorteados_em_claro(conn, sorteio, |s| {
+            chave.cifrar_sorteado(s)
+        });
+        if cifrados > 0 {
+            tracing::info!(
+                "Cifrado o sorteado de {cifrados} envio(s) antigo(s) do sorteio {sorteio}"
+            );
+        }
+
+        self.chaves.insert(sorteio, chave.clone());
+        chave
+    }
+
+    /// Semente do sorteio, decifrando-a se estiver selada.
+    ///
+    /// Retorna `None` se ela foi descartada
+    pub fn semente(&mut self, conn: &mut Connection, sorteio: &Sorteio) -> Option<String> {
+        if let Some(s) = &sorteio.seed {
+            return Some(s.clone());
+        }
+
+        let cifrada = sorteio.seed_cifrada.as_ref()?;
+        let chave = self.chave(conn, sorteio.id);
+        let bytes = chave.decifrar(cifrada).unwrap_or_else(|| {
+            panic!(
+                "Não foi possível decifrar a semente do sorteio {}",
+                sorteio.id
+            )
+        });
+
+        Some(String::from_utf8(bytes).unwrap())
+    }
+
+    /// Sorteado de um envio, seja ele cifrado ou de antes da cifragem (que é
+    /// cifrado nesse momento, com o resto do sorteio)
+    pub fn sorteado(&mut self, conn: &mut Connection, envio: &Envio) -> u64 {
+        let chave = self.chave(conn, envio.sorteio);
+        if let Some(s) = envio.sorteado {
+            return s;
+        }
+
+        chave
+            .decifrar_sorteado(envio.sorteado_cifrado.as_ref().unwrap())
+            .unwrap_or_else(|| panic!("Não foi possível decifrar o envio {}", envio.id))
+    }
+}
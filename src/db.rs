@@ -1,4 +1,7 @@
-use rusqlite::Connection;
+use rusqlite::{
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+    Connection, ToSql,
+};
 
 use crate::config::Config;
 
@@ -8,47 +11,241 @@ mod embedded {
     embed_migrations!("migrations");
 }
 
+/// Formato das datas guardadas na base, sempre em UTC, o mesmo do
+/// `CURRENT_TIMESTAMP` do SQLite
+pub const FORMATO_DATA: &str = "%Y-%m-%d %H:%M:%S";
+
 pub fn make_conn(config: &Config) -> Connection {
     let mut conn = Connection::open(config.db_path.clone()).unwrap();
+    migrar(&mut conn);
 
-    conn.execute("PRAGMA foreign_keys = ON;", []).unwrap();
-
-    embedded::migrations::runner().run(&mut conn).unwrap();
+    let em_claro = envios::count_sorteados_em_claro(&conn);
+    if em_claro > 0 {
+        tracing::warn!(
+            "{em_claro} envio(s) de antes da cifragem ainda guardam o sorteado em claro; \
+             eles são cifrados na próxima vez que a senha do organizador abrir o sorteio deles"
+        );
+    }
 
     conn
 }
 
+pub(crate) fn migrar(conn: &mut Connection) {
+    // as migrações que recriam tabelas precisam rodar sem as chaves estrangeiras,
+    // que vêm ligadas por padrão no SQLite embutido
+    conn.execute("PRAGMA foreign_keys = OFF;", []).unwrap();
+    embedded::migrations::runner().run(conn).unwrap();
+
+    conn.execute("PRAGMA foreign_keys = ON;", []).unwrap();
+}
+
 #[derive(Debug, Clone)]
 pub struct Jogo {
     pub id: u64,
     pub nome: String,
+    /// Dia da troca de presentes (`AAAA-MM-DD`), usado pelos lembretes
+    pub data_evento: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Jogador {
     pub id: u64,
     pub nome: String,
     pub email: String,
     pub jogo: u64,
+    /// Chave pública OpenPGP em ASCII armor; se presente, o email é cifrado
+    pub pgp_public_key: Option<String>,
+    /// Jogadores do mesmo grupo (uma família, um casal) não tiram uns aos outros
+    pub grupo: Option<String>,
+    /// Idioma preferido do jogador, como veio na importação (`pt`, `en`...)
+    pub idioma: Option<String>,
+    /// Sugestões de presente, uma por linha, mandadas a quem tirar o jogador
+    pub lista_desejos: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+impl std::fmt::Debug for Jogador {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jogador")
+            .field("id", &self.id)
+            .field("nome", &self.nome)
+            .field("email", &self.email)
+            .field("jogo", &self.jogo)
+            .field("pgp", &self.pgp_public_key.is_some())
+            .field("grupo", &self.grupo)
+            .field("idioma", &self.idioma)
+            .field("lista_desejos", &self.lista_desejos.is_some())
+            .finish()
+    }
+}
+
+/// A semente pode estar em claro (`seed`), selada com a senha do organizador
+/// (`seed_cifrada`) ou descartada, restando só o `compromisso` (SHA-256 dela)
+#[derive(Clone)]
 pub struct Sorteio {
     pub id: u64,
-    pub seed: String,
+    pub seed: Option<String>,
+    pub seed_cifrada: Option<Vec<u8>>,
+    pub compromisso: Option<String>,
     pub jogadores_hash: String,
     pub jogadores_qtd: u64,
     pub jogo: u64,
+    /// Quando as mensagens devem sair, em UTC (`YYYY-MM-DD HH:MM:SS`)
+    pub agendado_para: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+impl Sorteio {
+    fn estado_semente(&self) -> &str {
+        match (&self.seed, &self.seed_cifrada) {
+            (Some(seed), _) => seed,
+            (None, Some(_)) => "<selada>",
+            (None, None) => "<descartada>",
+        }
+    }
+}
+
+impl std::fmt::Debug for Sorteio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sorteio")
+            .field("id", &self.id)
+            .field("seed", &format_args!("{}", self.estado_semente()))
+            .field("compromisso", &self.compromisso)
+            .field("jogadores_hash", &self.jogadores_hash)
+            .field("jogadores_qtd", &self.jogadores_qtd)
+            .field("jogo", &self.jogo)
+            .field("agendado_para", &self.agendado_para)
+            .finish()
+    }
+}
+
+/// Visão de um `Sorteio` que esconde a semente, já que com ela e a lista de
+/// jogadores é possível recalcular todos os pares
+pub struct SorteioCego<'a>(pub &'a Sorteio);
+
+impl std::fmt::Debug for SorteioCego<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let seed = match self.0.seed {
+            Some(_) => "<oculta>",
+            None => self.0.estado_semente(),
+        };
+
+        f.debug_struct("Sorteio")
+            .field("id", &self.0.id)
+            .field("seed", &format_args!("{}", seed))
+            .field("compromisso", &self.0.compromisso)
+            .field("jogadores_hash", &self.0.jogadores_hash)
+            .field("jogadores_qtd", &self.0.jogadores_qtd)
+            .field("jogo", &self.0.jogo)
+            .field("agendado_para", &self.0.agendado_para)
+            .finish()
+    }
+}
+
+/// O que um envio comunicou ao destino
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TipoEnvio {
+    /// O resultado do sorteio
+    Sorteio,
+    /// Lembrete de quem o destino tirou, perto da data do evento
+    Lembrete,
+    /// A corrente inteira, mandada a todos depois do evento
+    Revelacao,
+}
+
+impl ToSql for TipoEnvio {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            TipoEnvio::Sorteio => "sorteio",
+            TipoEnvio::Lembrete => "lembrete",
+            TipoEnvio::Revelacao => "revelacao",
+        }
+        .into())
+    }
+}
+
+impl FromSql for TipoEnvio {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "sorteio" => Ok(TipoEnvio::Sorteio),
+            "lembrete" => Ok(TipoEnvio::Lembrete),
+            "revelacao" => Ok(TipoEnvio::Revelacao),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// `sorteado` só existe em envios anteriores à cifragem; os novos guardam
+/// apenas `sorteado_cifrado`, que é decifrado pelo `cripto::Chaveiro`
+#[derive(Clone)]
 pub struct Envio {
     pub id: u64,
     pub sorteio: u64,
     pub destino: u64,
-    pub sorteado: u64,
+    pub sorteado: Option<u64>,
+    pub sorteado_cifrado: Option<Vec<u8>>,
     pub sucesso: bool,
     pub erro: Option<String>,
+    pub tipo: TipoEnvio,
+    /// `Message-ID` da mensagem entregue, usado para casar os bounces
+    pub message_id: Option<String>,
+    /// Quando o jogador abriu o link de confirmação da mensagem
+    pub confirmado_em: Option<String>,
+}
+
+impl std::fmt::Debug for Envio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Envio")
+            .field("id", &self.id)
+            .field("sorteio", &self.sorteio)
+            .field("destino", &self.destino)
+            .field("sorteado", &self.sorteado)
+            .field("sucesso", &self.sucesso)
+            .field("erro", &self.erro)
+            .field("tipo", &self.tipo)
+            .field("message_id", &self.message_id)
+            .field("confirmado_em", &self.confirmado_em)
+            .finish()
+    }
+}
+
+/// Visão de um `Envio` que esconde o sorteado, usada nas listagens do modo cego
+pub struct EnvioCego<'a>(pub &'a Envio);
+
+impl std::fmt::Debug for EnvioCego<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Envio")
+            .field("id", &self.0.id)
+            .field("sorteio", &self.0.sorteio)
+            .field("destino", &self.0.destino)
+            .field("sorteado", &format_args!("<oculto>"))
+            .field("sucesso", &self.0.sucesso)
+            .field("erro", &self.0.erro)
+            .field("tipo", &self.0.tipo)
+            .field("message_id", &self.0.message_id)
+            .field("confirmado_em", &self.0.confirmado_em)
+            .finish()
+    }
+}
+
+/// Mensagem já montada esperando o `worker` entregá-la.
+///
+/// A mensagem é guardada cifrada com a chave do sorteio, já que ela contém o
+/// sorteado em claro
+#[derive(Clone)]
+pub struct ItemOutbox {
+    pub id: u64,
+    pub sorteio: u64,
+    pub destino: u64,
+    pub sorteado_cifrado: Vec<u8>,
+    pub remetente: String,
+    pub destinatario: String,
+    pub mensagem_cifrada: Vec<u8>,
+    pub tentativas: u32,
+    pub ultimo_erro: Option<String>,
+    pub tipo: TipoEnvio,
+    pub message_id: Option<String>,
+    /// Token do link de confirmação, que passa para o envio
+    pub token: Option<String>,
 }
 
 /// Contém funções que abstraem TODAS as conexões com a base de dados
@@ -58,7 +255,9 @@ pub mod jogo {
     use rusqlite::{params, Connection};
 
     pub fn get_all_jogos(conn: &mut Connection) -> Vec<Jogo> {
-        let mut query = conn.prepare("SELECT id, nome FROM jogos").unwrap();
+        let mut query = conn
+            .prepare("SELECT id, nome, data_evento FROM jogos")
+            .unwrap();
         let jogos = query
             .query_map((), |row| Ok(extract_jogo(row)))
             .unwrap()
@@ -67,7 +266,7 @@ pub mod jogo {
         jogos
     }
 
-    pub fn create_jogo_with_nome(conn: &mut Connection, nome: &String) -> usize {
+    pub fn create_jogo_with_nome(conn: &Connection, nome: &String) -> usize {
         let mut query = conn
             .prepare("INSERT INTO jogos (nome) VALUES (?1) RETURNING id")
             .unwrap();
@@ -89,7 +288,7 @@ pub mod jogo {
 
     pub fn get_jogo_by_id(conn: &mut Connection, id: u64) -> Jogo {
         let mut query = conn
-            .prepare("SELECT id, nome FROM jogos WHERE id=?1")
+            .prepare("SELECT id, nome, data_evento FROM jogos WHERE id=?1")
             .unwrap();
 
         query
@@ -97,10 +296,45 @@ pub mod jogo {
             .unwrap()
     }
 
+    pub fn exists_jogo(conn: &mut Connection, id: u64) -> bool {
+        conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM jogos WHERE id=?1)",
+            params![id],
+            |x| x.get(0),
+        )
+        .unwrap()
+    }
+
+    pub fn get_jogos_com_evento(conn: &mut Connection) -> Vec<Jogo> {
+        let mut query = conn
+            .prepare("SELECT id, nome, data_evento FROM jogos WHERE data_evento IS NOT NULL")
+            .unwrap();
+
+        query
+            .query_map(params![], |x| Ok(extract_jogo(x)))
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+    }
+
+    pub fn update_nome(conn: &mut Connection, id: u64, nome: &String) -> usize {
+        conn.execute("UPDATE jogos SET nome = ?1 WHERE id=?2", params![nome, id])
+            .unwrap()
+    }
+
+    pub fn update_data_evento(conn: &Connection, id: u64, data: Option<&String>) -> usize {
+        conn.execute(
+            "UPDATE jogos SET data_evento = ?1 WHERE id=?2",
+            params![data, id],
+        )
+        .unwrap()
+    }
+
     fn extract_jogo(row: &rusqlite::Row<'_>) -> Jogo {
         Jogo {
             id: row.get(0).unwrap(),
             nome: row.get(1).unwrap(),
+            data_evento: row.get(2).unwrap(),
         }
     }
 }
@@ -109,9 +343,9 @@ pub mod jogador {
     use super::Jogador;
     use rusqlite::{params, Connection};
 
-    pub fn get_jogadores_by_jogo(conn: &mut Connection, jogo: u64) -> Vec<Jogador> {
+    pub fn get_jogadores_by_jogo(conn: &Connection, jogo: u64) -> Vec<Jogador> {
         let mut query = conn
-            .prepare("SELECT id, nome, email, jogo FROM jogadores WHERE jogo=?1")
+            .prepare("SELECT id, nome, email, jogo, pgp_public_key, grupo, idioma, lista_desejos FROM jogadores WHERE jogo=?1")
             .unwrap();
 
         let jogadores = query
@@ -125,7 +359,7 @@ pub mod jogador {
 
     pub fn get_all_jogadores(conn: &mut Connection) -> Vec<Jogador> {
         let mut query = conn
-            .prepare("SELECT id, nome, email, jogo FROM jogadores")
+            .prepare("SELECT id, nome, email, jogo, pgp_public_key, grupo, idioma, lista_desejos FROM jogadores")
             .unwrap();
 
         let jogadores = query
@@ -137,7 +371,7 @@ pub mod jogador {
         jogadores
     }
 
-    pub fn create_jogador(conn: &mut Connection, jogo: u64, nome: String, email: String) -> usize {
+    pub fn create_jogador(conn: &Connection, jogo: u64, nome: String, email: String) -> usize {
         let mut query = conn
             .prepare("INSERT INTO jogadores (jogo, nome, email) VALUES (?1, ?2, ?3) RETURNING id")
             .unwrap();
@@ -147,9 +381,9 @@ pub mod jogador {
             .unwrap()
     }
 
-    pub fn get_jogador_by_id(conn: &mut Connection, id: u64) -> Jogador {
+    pub fn get_jogador_by_id(conn: &Connection, id: u64) -> Jogador {
         let mut query = conn
-            .prepare("SELECT id, nome, email, jogo FROM jogadores WHERE id=?1")
+            .prepare("SELECT id, nome, email, jogo, pgp_public_key, grupo, idioma, lista_desejos FROM jogadores WHERE id=?1")
             .unwrap();
 
         query
@@ -158,7 +392,7 @@ pub mod jogador {
     }
 
     pub fn update_jogador_by_collumn(
-        conn: &mut Connection,
+        conn: &Connection,
         collumn: &String,
         new_value: String,
         id: u64,
@@ -174,6 +408,31 @@ pub mod jogador {
             .unwrap()
     }
 
+    /// Atualiza os campos opcionais que vêm das importações
+    pub fn update_preferencias(
+        conn: &Connection,
+        id: u64,
+        grupo: Option<&String>,
+        idioma: Option<&String>,
+        lista_desejos: Option<&String>,
+    ) -> usize {
+        conn.execute(
+            "UPDATE jogadores SET grupo = ?1, idioma = ?2, lista_desejos = ?3 WHERE id=?4",
+            params![grupo, idioma, lista_desejos, id],
+        )
+        .unwrap()
+    }
+
+    pub fn update_pgp_public_key(conn: &mut Connection, id: u64, chave: Option<String>) -> usize {
+        let mut query = conn
+            .prepare("UPDATE jogadores SET pgp_public_key = ?1 WHERE id=?2 RETURNING id")
+            .unwrap();
+
+        query
+            .query_row(params![chave, id], |x| Ok(x.get(0).unwrap()))
+            .unwrap()
+    }
+
     pub fn delete_jogador_by_id(conn: &mut Connection, id: u64) -> usize {
         let mut query = conn
             .prepare("DELETE FROM jogadores WHERE id=?1 RETURNING id")
@@ -190,6 +449,10 @@ pub mod jogador {
             nome: row.get(1).unwrap(),
             email: row.get(2).unwrap(),
             jogo: row.get(3).unwrap(),
+            pgp_public_key: row.get(4).unwrap(),
+            grupo: row.get(5).unwrap(),
+            idioma: row.get(6).unwrap(),
+            lista_desejos: row.get(7).unwrap(),
         }
     }
 }
@@ -198,12 +461,12 @@ pub mod sorteio {
     use std::hash::Hasher;
 
     use super::{Jogador, Sorteio};
-    use rusqlite::{params, Connection};
+    use rusqlite::{params, Connection, OptionalExtension};
 
     pub fn get_sorteio_by_id(conn: &mut Connection, id: &u64) -> Sorteio {
         let mut query = conn
             .prepare(
-                "SELECT id, seed, jogadores_hash, jogadores_qtd, jogo FROM sorteios WHERE id=?1",
+                "SELECT id, seed, seed_cifrada, compromisso, jogadores_hash, jogadores_qtd, jogo, agendado_para FROM sorteios WHERE id=?1",
             )
             .unwrap();
         query
@@ -214,7 +477,7 @@ pub mod sorteio {
     pub fn get_sorteios_by_jogo(conn: &mut Connection, jogo: u64) -> Vec<Sorteio> {
         let mut query = conn
             .prepare(
-                "SELECT id, seed, jogadores_hash, jogadores_qtd, jogo FROM sorteios WHERE jogo=?1",
+                "SELECT id, seed, seed_cifrada, compromisso, jogadores_hash, jogadores_qtd, jogo, agendado_para FROM sorteios WHERE jogo=?1",
             )
             .unwrap();
 
@@ -227,7 +490,7 @@ pub mod sorteio {
 
     pub fn get_sorteios(conn: &mut Connection) -> Vec<Sorteio> {
         let mut query = conn
-            .prepare("SELECT id, seed, jogadores_hash, jogadores_qtd, jogo FROM sorteios")
+            .prepare("SELECT id, seed, seed_cifrada, compromisso, jogadores_hash, jogadores_qtd, jogo, agendado_para FROM sorteios")
             .unwrap();
 
         query
@@ -239,19 +502,21 @@ pub mod sorteio {
 
     pub fn create_sorteio<T: Hasher>(
         conn: &mut Connection,
-        seed: &String,
+        seed: Option<&String>,
+        compromisso: &String,
         jogo: u64,
         hasher: T,
         jogadores: Vec<Jogador>,
     ) -> usize {
         let mut query = conn
-            .prepare("INSERT INTO sorteios (seed, jogo, jogadores_hash, jogadores_qtd) VALUES (?1, ?2, ?3, ?4) RETURNING id")
+            .prepare("INSERT INTO sorteios (seed, compromisso, jogo, jogadores_hash, jogadores_qtd) VALUES (?1, ?2, ?3, ?4, ?5) RETURNING id")
             .unwrap();
 
         query
             .query_row(
                 params![
-                    seed.to_string(),
+                    seed,
+                    compromisso,
                     jogo,
                     hasher.finish().to_string(),
                     jogadores.len()
@@ -261,29 +526,86 @@ pub mod sorteio {
             .unwrap()
     }
 
+    /// Sal e verificador usados pelo `cripto::Chaveiro`
+    pub fn get_cifragem(conn: &mut Connection, id: u64) -> (Option<String>, Option<Vec<u8>>) {
+        let mut query = conn
+            .prepare("SELECT sal, verificador FROM sorteios WHERE id=?1")
+            .unwrap();
+
+        query
+            .query_row(params![id], |x| Ok((x.get(0).unwrap(), x.get(1).unwrap())))
+            .unwrap()
+    }
+
+    pub fn update_cifragem(conn: &mut Connection, id: u64, sal: &String, verificador: Vec<u8>) {
+        conn.execute(
+            "UPDATE sorteios SET sal = ?1, verificador = ?2 WHERE id=?3",
+            params![sal, verificador, id],
+        )
+        .unwrap();
+    }
+
+    /// Substitui a semente guardada; passar ambos `None` a descarta
+    pub fn update_semente(
+        conn: &mut Connection,
+        id: u64,
+        seed: Option<&String>,
+        seed_cifrada: Option<Vec<u8>>,
+        compromisso: &String,
+    ) {
+        conn.execute(
+            "UPDATE sorteios SET seed = ?1, seed_cifrada = ?2, compromisso = ?3 WHERE id=?4",
+            params![seed, seed_cifrada, compromisso, id],
+        )
+        .unwrap();
+    }
+
+    /// Sorteio mais recente do jogo que já entregou algum resultado
+    pub fn get_ultimo_sorteio_enviado(conn: &mut Connection, jogo: u64) -> Option<Sorteio> {
+        let mut query = conn
+            .prepare("SELECT id, seed, seed_cifrada, compromisso, jogadores_hash, jogadores_qtd, jogo, agendado_para FROM sorteios WHERE jogo=?1 AND EXISTS (SELECT 1 FROM envios WHERE envios.sorteio = sorteios.id AND envios.tipo = 'sorteio' AND envios.sucesso) ORDER BY id DESC LIMIT 1")
+            .unwrap();
+
+        query
+            .query_row(params![jogo], |x| Ok(extract_sorteio(x)))
+            .optional()
+            .unwrap()
+    }
+
+    pub fn update_agendamento(conn: &mut Connection, id: u64, agendado_para: Option<&String>) {
+        conn.execute(
+            "UPDATE sorteios SET agendado_para = ?1 WHERE id=?2",
+            params![agendado_para, id],
+        )
+        .unwrap();
+    }
+
     fn extract_sorteio(row: &rusqlite::Row<'_>) -> Sorteio {
-        return Sorteio {
+        Sorteio {
             id: row.get(0).unwrap(),
             seed: row.get(1).unwrap(),
-            jogadores_hash: row.get(2).unwrap(),
-            jogadores_qtd: row.get(3).unwrap(),
-            jogo: row.get(4).unwrap(),
-        };
+            seed_cifrada: row.get(2).unwrap(),
+            compromisso: row.get(3).unwrap(),
+            jogadores_hash: row.get(4).unwrap(),
+            jogadores_qtd: row.get(5).unwrap(),
+            jogo: row.get(6).unwrap(),
+            agendado_para: row.get(7).unwrap(),
+        }
     }
 }
 
 pub mod envios {
     use super::Envio;
-    use rusqlite::{params, Connection};
+    use rusqlite::{params, Connection, OptionalExtension};
 
     pub fn get_envios_by_sorteio(conn: &mut Connection, sorteio: u64) -> Vec<Envio> {
         let mut query = conn
             .prepare(
-                "SELECT id, sorteio, destino, sorteado, sucesso, erro FROM envios WHERE sorteio=?1",
+                "SELECT id, sorteio, destino, sorteado, sorteado_cifrado, sucesso, erro, tipo, message_id, confirmado_em FROM envios WHERE sorteio=?1",
             )
             .unwrap();
         query
-            .query_map(params![sorteio], |x| extract_envio(x))
+            .query_map(params![sorteio], extract_envio)
             .unwrap()
             .map(|x| x.unwrap())
             .collect()
@@ -291,7 +613,7 @@ pub mod envios {
 
     pub fn get_envio_by_id(conn: &mut Connection, envio: u64) -> Envio {
         let mut query = conn
-            .prepare("SELECT id, sorteio, destino, sorteado, sucesso, erro FROM envios WHERE id=?1")
+            .prepare("SELECT id, sorteio, destino, sorteado, sorteado_cifrado, sucesso, erro, tipo, message_id, confirmado_em FROM envios WHERE id=?1")
             .unwrap();
 
         query.query_row(params![envio], extract_envio).unwrap()
@@ -299,7 +621,7 @@ pub mod envios {
 
     pub fn get_all_envios(conn: &mut Connection) -> Vec<Envio> {
         let mut query = conn
-            .prepare("SELECT id, sorteio, destino, sorteado, sucesso, erro FROM envios")
+            .prepare("SELECT id, sorteio, destino, sorteado, sorteado_cifrado, sucesso, erro, tipo, message_id, confirmado_em FROM envios")
             .unwrap();
 
         query
@@ -309,6 +631,129 @@ pub mod envios {
             .collect()
     }
 
+    /// Resultados do sorteio entregues com sucesso cujo destino ainda não
+    /// recebeu (nem tem na fila) um lembrete
+    pub fn get_envios_sem_lembrete(conn: &mut Connection, sorteio: u64) -> Vec<Envio> {
+        let mut query = conn
+            .prepare(
+                "SELECT e.id, e.sorteio, e.destino, e.sorteado, e.sorteado_cifrado, e.sucesso, e.erro, e.tipo, e.message_id, e.confirmado_em FROM envios e
+                WHERE e.sorteio = ?1 AND e.tipo = 'sorteio' AND e.sucesso
+                AND NOT EXISTS (SELECT 1 FROM envios l WHERE l.sorteio = e.sorteio AND l.destino = e.destino AND l.tipo = 'lembrete' AND l.sucesso)
+                AND NOT EXISTS (SELECT 1 FROM outbox o WHERE o.sorteio = e.sorteio AND o.destino = e.destino AND o.tipo = 'lembrete')",
+            )
+            .unwrap();
+
+        query
+            .query_map(params![sorteio], extract_envio)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+    }
+
+    pub fn get_envio_by_message_id(conn: &mut Connection, message_id: &str) -> Option<Envio> {
+        let mut query = conn
+            .prepare("SELECT id, sorteio, destino, sorteado, sorteado_cifrado, sucesso, erro, tipo, message_id, confirmado_em FROM envios WHERE message_id=?1")
+            .unwrap();
+
+        query
+            .query_row(params![message_id], extract_envio)
+            .optional()
+            .unwrap()
+    }
+
+    pub fn get_envio_by_token(conn: &mut Connection, token: &str) -> Option<Envio> {
+        let mut query = conn
+            .prepare("SELECT id, sorteio, destino, sorteado, sorteado_cifrado, sucesso, erro, tipo, message_id, confirmado_em FROM envios WHERE token=?1")
+            .unwrap();
+
+        query
+            .query_row(params![token], extract_envio)
+            .optional()
+            .unwrap()
+    }
+
+    /// Destinos que receberam o link de confirmação do resultado do sorteio e
+    /// ainda não confirmaram nenhum deles
+    pub fn get_destinos_sem_confirmacao(conn: &mut Connection, sorteio: u64) -> Vec<u64> {
+        let mut query = conn
+            .prepare(
+                "SELECT DISTINCT e.destino FROM envios e
+                WHERE e.sorteio = ?1 AND e.tipo = 'sorteio' AND e.sucesso AND e.token IS NOT NULL
+                AND NOT EXISTS (SELECT 1 FROM envios c WHERE c.sorteio = e.sorteio AND c.destino = e.destino AND c.tipo = 'sorteio' AND c.confirmado_em IS NOT NULL)",
+            )
+            .unwrap();
+
+        query
+            .query_map(params![sorteio], |x| x.get(0))
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+    }
+
+    /// Marca o envio do token como confirmado, mantendo a primeira confirmação;
+    /// `None` se o token não existir
+    pub fn confirmar(conn: &mut Connection, token: &str) -> Option<Envio> {
+        let id: Option<u64> = conn
+            .query_row(
+                "UPDATE envios SET confirmado_em = COALESCE(confirmado_em, CURRENT_TIMESTAMP) WHERE token=?1 RETURNING id",
+                params![token],
+                |x| x.get(0),
+            )
+            .optional()
+            .unwrap();
+
+        id.map(|id| get_envio_by_id(conn, id))
+    }
+
+    /// Marca como falho um envio que o servidor aceitou mas depois devolveu
+    pub fn update_bounce(conn: &mut Connection, envio: u64, motivo: &str) -> usize {
+        conn.execute(
+            "UPDATE envios SET sucesso = false, erro = ?1 WHERE id=?2",
+            params![motivo, envio],
+        )
+        .unwrap()
+    }
+
+    /// Envios de antes da cifragem, que ainda guardam o sorteado em claro
+    pub fn count_sorteados_em_claro(conn: &Connection) -> usize {
+        conn.query_row(
+            "SELECT COUNT(*) FROM envios WHERE sorteado IS NOT NULL",
+            [],
+            |x| x.get(0),
+        )
+        .unwrap()
+    }
+
+    /// Move o sorteado em claro dos envios do sorteio para `sorteado_cifrado`.
+    ///
+    /// Retorna quantos envios foram cifrados
+    pub fn cifrar_sorteados_em_claro(
+        conn: &mut Connection,
+        sorteio: u64,
+        cifrar: impl Fn(u64) -> Vec<u8>,
+    ) -> usize {
+        let tx = conn.transaction().unwrap();
+
+        let envios: Vec<(u64, u64)> = tx
+            .prepare("SELECT id, sorteado FROM envios WHERE sorteio = ?1 AND sorteado IS NOT NULL")
+            .unwrap()
+            .query_map(params![sorteio], |x| Ok((x.get(0)?, x.get(1)?)))
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+
+        for (id, sorteado) in &envios {
+            tx.execute(
+                "UPDATE envios SET sorteado_cifrado = ?1, sorteado = NULL WHERE id = ?2",
+                params![cifrar(*sorteado), id],
+            )
+            .unwrap();
+        }
+
+        tx.commit().unwrap();
+        envios.len()
+    }
+
     pub fn delete_envios_by_sorteio(conn: &mut Connection, sorteio: u64) -> Vec<usize> {
         let mut query = conn
             .prepare("DELETE FROM envios WHERE sorteio = ?1 RETURNING id")
@@ -337,8 +782,325 @@ pub mod envios {
             sorteio: x.get(1).unwrap(),
             destino: x.get(2).unwrap(),
             sorteado: x.get(3).unwrap(),
-            sucesso: x.get(4).unwrap(),
-            erro: x.get(5).unwrap(),
+            sorteado_cifrado: x.get(4).unwrap(),
+            sucesso: x.get(5).unwrap(),
+            erro: x.get(6).unwrap(),
+            tipo: x.get(7).unwrap(),
+            message_id: x.get(8).unwrap(),
+            confirmado_em: x.get(9).unwrap(),
         })
     }
 }
+
+pub mod outbox {
+    use std::collections::BTreeSet;
+
+    use super::{ItemOutbox, TipoEnvio};
+    use rusqlite::{params, Connection, OptionalExtension};
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_item(
+        conn: &Connection,
+        sorteio: u64,
+        destino: u64,
+        sorteado_cifrado: Vec<u8>,
+        remetente: String,
+        destinatario: String,
+        mensagem_cifrada: Vec<u8>,
+        disponivel_em: Option<&String>,
+        tipo: TipoEnvio,
+        message_id: Option<&str>,
+        token: Option<&str>,
+    ) -> usize {
+        let mut query = conn
+            .prepare("INSERT INTO outbox (sorteio, destino, sorteado_cifrado, remetente, destinatario, mensagem_cifrada, proxima_tentativa, tipo, message_id, token) VALUES (?1, ?2, ?3, ?4, ?5, ?6, COALESCE(?7, CURRENT_TIMESTAMP), ?8, ?9, ?10) RETURNING id")
+            .unwrap();
+
+        query
+            .query_row(
+                params![
+                    sorteio,
+                    destino,
+                    sorteado_cifrado,
+                    remetente,
+                    destinatario,
+                    mensagem_cifrada,
+                    disponivel_em,
+                    tipo,
+                    message_id,
+                    token
+                ],
+                |x| Ok(x.get(0).unwrap()),
+            )
+            .unwrap()
+    }
+
+    /// Itens cuja próxima tentativa já chegou
+    pub fn get_itens_prontos(conn: &mut Connection) -> Vec<ItemOutbox> {
+        let mut query = conn
+            .prepare("SELECT id, sorteio, destino, sorteado_cifrado, remetente, destinatario, mensagem_cifrada, tentativas, ultimo_erro, tipo, message_id, token FROM outbox WHERE proxima_tentativa <= datetime('now')")
+            .unwrap();
+
+        query
+            .query_map(params![], extract_item)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+    }
+
+    pub fn get_itens_by_sorteio(conn: &mut Connection, sorteio: u64) -> Vec<ItemOutbox> {
+        let mut query = conn
+            .prepare("SELECT id, sorteio, destino, sorteado_cifrado, remetente, destinatario, mensagem_cifrada, tentativas, ultimo_erro, tipo, message_id, token FROM outbox WHERE sorteio = ?1")
+            .unwrap();
+
+        query
+            .query_map(params![sorteio], extract_item)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+    }
+
+    /// Segundos até o próximo item ficar disponível, ou `None` se a fila está vazia
+    pub fn segundos_ate_proximo(conn: &mut Connection, ignorar: &BTreeSet<u64>) -> Option<u64> {
+        let mut query = conn
+            .prepare(&format!("SELECT MAX(0, CAST(ROUND((julianday(MIN(proxima_tentativa)) - julianday('now')) * 86400) AS INTEGER)) FROM outbox WHERE sorteio NOT IN ({}) HAVING COUNT(*) > 0", lista(ignorar)))
+            .unwrap();
+
+        query
+            .query_row(params![], |x| Ok(x.get(0).unwrap()))
+            .optional()
+            .unwrap()
+    }
+
+    /// Como `segundos_ate_proximo`, mas só entre os itens que já falharam ao
+    /// menos uma vez, ignorando os que nunca foram tentados (os agendados)
+    pub fn segundos_ate_nova_tentativa(
+        conn: &mut Connection,
+        ignorar: &BTreeSet<u64>,
+    ) -> Option<u64> {
+        let mut query = conn
+            .prepare(&format!("SELECT MAX(0, CAST(ROUND((julianday(MIN(proxima_tentativa)) - julianday('now')) * 86400) AS INTEGER)) FROM outbox WHERE tentativas > 0 AND sorteio NOT IN ({}) HAVING COUNT(*) > 0", lista(ignorar)))
+            .unwrap();
+
+        query
+            .query_row(params![], |x| Ok(x.get(0).unwrap()))
+            .optional()
+            .unwrap()
+    }
+
+    /// Ids para um `IN (...)`, já que são números
+    fn lista(ids: &BTreeSet<u64>) -> String {
+        ids.iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn count_itens(conn: &mut Connection, ignorar: &BTreeSet<u64>) -> usize {
+        conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM outbox WHERE sorteio NOT IN ({})",
+                lista(ignorar)
+            ),
+            params![],
+            |x| Ok(x.get(0).unwrap()),
+        )
+        .unwrap()
+    }
+
+    /// Devolve o item para a fila, com a próxima tentativa daqui a `segundos`
+    pub fn reagendar(conn: &Connection, id: u64, erro: &str, segundos: u64) -> usize {
+        conn.execute(
+            "UPDATE outbox SET tentativas = tentativas + 1, ultimo_erro = ?2, proxima_tentativa = datetime('now', '+' || ?3 || ' seconds') WHERE id = ?1",
+            params![id, erro, segundos],
+        )
+        .unwrap()
+    }
+
+    pub fn delete_item_by_id(conn: &Connection, id: u64) -> usize {
+        conn.execute("DELETE FROM outbox WHERE id = ?1", params![id])
+            .unwrap()
+    }
+
+    pub fn delete_itens_by_sorteio(conn: &mut Connection, sorteio: u64) -> Vec<usize> {
+        let mut query = conn
+            .prepare("DELETE FROM outbox WHERE sorteio = ?1 RETURNING id")
+            .unwrap();
+
+        query
+            .query_map(params![sorteio], |x| Ok(x.get(0).unwrap()))
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+    }
+
+    fn extract_item(x: &rusqlite::Row<'_>) -> Result<ItemOutbox, rusqlite::Error> {
+        Ok(ItemOutbox {
+            id: x.get(0).unwrap(),
+            sorteio: x.get(1).unwrap(),
+            destino: x.get(2).unwrap(),
+            sorteado_cifrado: x.get(3).unwrap(),
+            remetente: x.get(4).unwrap(),
+            destinatario: x.get(5).unwrap(),
+            mensagem_cifrada: x.get(6).unwrap(),
+            tentativas: x.get(7).unwrap(),
+            ultimo_erro: x.get(8).unwrap(),
+            tipo: x.get(9).unwrap(),
+            message_id: x.get(10).unwrap(),
+            token: x.get(11).unwrap(),
+        })
+    }
+}
+
+/// Pares (jogador, excluído) em que o jogador não pode tirar o excluído
+pub mod exclusoes {
+    use rusqlite::{params, Connection};
+
+    pub fn get_exclusoes_by_jogo(conn: &Connection, jogo: u64) -> Vec<(u64, u64)> {
+        let mut query = conn
+            .prepare(
+                "SELECT e.jogador, e.excluido FROM exclusoes e JOIN jogadores j ON j.id = e.jogador WHERE j.jogo = ?1",
+            )
+            .unwrap();
+
+        query
+            .query_map(params![jogo], |x| {
+                Ok((x.get(0).unwrap(), x.get(1).unwrap()))
+            })
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+    }
+
+    /// Troca todas as exclusões do jogador pelas dadas; chamada dentro da
+    /// transação da importação
+    pub fn replace_exclusoes(conn: &Connection, jogador: u64, excluidos: &[u64]) {
+        conn.execute("DELETE FROM exclusoes WHERE jogador = ?1", params![jogador])
+            .unwrap();
+        for excluido in excluidos {
+            conn.execute(
+                "INSERT OR IGNORE INTO exclusoes (jogador, excluido) VALUES (?1, ?2)",
+                params![jogador, excluido],
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Registro de auditoria de cada vez que um sorteado foi mostrado na CLI
+pub mod revelacoes {
+    use rusqlite::{params, Connection};
+
+    pub fn create_revelacao(
+        conn: &mut Connection,
+        envio: u64,
+        sorteio: u64,
+        comando: &str,
+    ) -> usize {
+        let mut query = conn
+            .prepare(
+                "INSERT INTO revelacoes (envio, sorteio, comando) VALUES (?1, ?2, ?3) RETURNING id",
+            )
+            .unwrap();
+
+        query
+            .query_row(params![envio, sorteio, comando], |x| Ok(x.get(0).unwrap()))
+            .unwrap()
+    }
+}
+
+/// Cópia da base inteira pela API de backup online do SQLite, que copia uma
+/// imagem consistente mesmo com o worker escrevendo ao mesmo tempo
+pub mod backup {
+    use std::path::Path;
+
+    use rusqlite::{Connection, DatabaseName, OpenFlags};
+
+    use super::embedded;
+
+    /// Versão da última migração que este binário conhece
+    pub fn versao_embutida() -> u32 {
+        embedded::migrations::runner()
+            .get_migrations()
+            .iter()
+            .map(|m| m.version())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn salvar(conn: &Connection, destino: &Path) -> Result<(), String> {
+        if destino.exists() {
+            return Err(format!("{} já existe", destino.display()));
+        }
+
+        conn.backup(DatabaseName::Main, destino, None)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Confere se `arquivo` é uma base do amigo-cli que este binário sabe
+    /// abrir e devolve a versão do esquema dela.
+    ///
+    /// Cada migração registrada no arquivo precisa existir aqui com o mesmo
+    /// nome e checksum; um arquivo de uma versão mais nova do programa, ou com
+    /// migrações alteradas, é recusado
+    pub fn verificar(arquivo: &Path) -> Result<u32, String> {
+        let conn = Connection::open_with_flags(arquivo, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| e.to_string())?;
+
+        let integridade: String = conn
+            .query_row("PRAGMA quick_check", [], |r| r.get(0))
+            .map_err(|e| format!("não é uma base SQLite válida: {e}"))?;
+        if integridade != "ok" {
+            return Err(format!("base corrompida: {integridade}"));
+        }
+
+        let mut query = conn
+            .prepare("SELECT version, name, checksum FROM refinery_schema_history")
+            .map_err(|_| "não é uma base do amigo-cli (sem histórico de migrações)".to_owned())?;
+        let aplicadas = query
+            .query_map([], |r| {
+                Ok((
+                    r.get::<_, u32>(0)?,
+                    r.get::<_, String>(1)?,
+                    r.get::<_, String>(2)?,
+                ))
+            })
+            .and_then(|r| r.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| e.to_string())?;
+
+        let embutidas = embedded::migrations::runner().get_migrations().clone();
+        for (versao, nome, checksum) in &aplicadas {
+            match embutidas.iter().find(|m| m.version() == *versao) {
+                None => {
+                    return Err(format!(
+                        "a migração V{versao}__{nome} não existe nesta versão do programa \
+                         (esquema {}); atualize o amigo-cli",
+                        versao_embutida()
+                    ))
+                }
+                Some(m) if m.name() != nome || m.checksum().to_string() != *checksum => {
+                    return Err(format!(
+                        "a migração V{versao}__{nome} do arquivo difere da deste programa"
+                    ))
+                }
+                Some(_) => (),
+            }
+        }
+
+        Ok(aplicadas.iter().map(|(v, _, _)| *v).max().unwrap_or(0))
+    }
+
+    /// Substitui a base de `conn` pelo conteúdo de `origem`, já verificado, e
+    /// aplica as migrações que faltarem nele
+    pub fn restaurar(conn: &mut Connection, origem: &Path) -> Result<(), String> {
+        conn.restore(
+            DatabaseName::Main,
+            origem,
+            None::<fn(rusqlite::backup::Progress)>,
+        )
+        .map_err(|e| e.to_string())?;
+        super::migrar(conn);
+
+        Ok(())
+    }
+}
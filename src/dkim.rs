@@ -0,0 +1,388 @@
+//! Assinatura DKIM das mensagens enviadas e verificação local dela.
+//!
+//! A assinatura é feita pelo `lettre`, sempre com canonicalização
+//! `relaxed/relaxed`: com `simple` nos cabeçalhos, a dobra do próprio
+//! DKIM-Signature muda depois de preenchido o `b=` e os verificadores rejeitam
+//! a assinatura. A verificação aqui é independente e usa a chave pública
+//! derivada da chave privada configurada, sem consultar o DNS.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lettre::message::{
+    dkim::{
+        DkimCanonicalization, DkimCanonicalizationType, DkimConfig, DkimSigningAlgorithm,
+        DkimSigningKey,
+    },
+    header::HeaderName,
+};
+use rsa::{
+    pkcs1::DecodeRsaPrivateKey, pkcs8::EncodePublicKey, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey,
+};
+use sha2::{Digest, Sha256};
+
+pub struct Dkim {
+    pub seletor: String,
+    pub dominio: String,
+    pub algoritmo: DkimSigningAlgorithm,
+    /// PEM PKCS#1 para RSA, ou os 32 bytes da chave em base64 para Ed25519
+    pub chave_privada: String,
+}
+
+impl Dkim {
+    /// Lê `DKIM_SELECTOR`, `DKIM_DOMAIN`, `DKIM_PRIVATE_KEY` (caminho) e
+    /// `DKIM_ALGORITHM` (`rsa` ou `ed25519`). Sem seletor, DKIM fica desligado
+    pub fn from_env() -> Option<Dkim> {
+        let seletor = std::env::var("DKIM_SELECTOR").ok()?;
+        let algoritmo = match std::env::var("DKIM_ALGORITHM")
+            .unwrap_or("rsa".to_owned())
+            .as_str()
+        {
+            "rsa" => DkimSigningAlgorithm::Rsa,
+            "ed25519" => DkimSigningAlgorithm::Ed25519,
+            outro => panic!("DKIM_ALGORITHM desconhecido: {outro}"),
+        };
+
+        Some(Dkim {
+            seletor,
+            dominio: std::env::var("DKIM_DOMAIN").unwrap(),
+            algoritmo,
+            chave_privada: std::fs::read_to_string(std::env::var("DKIM_PRIVATE_KEY").unwrap())
+                .unwrap()
+                .trim()
+                .to_owned(),
+        })
+    }
+
+    pub fn config(&self) -> DkimConfig {
+        DkimConfig::new(
+            self.seletor.clone(),
+            self.dominio.clone(),
+            DkimSigningKey::new(&self.chave_privada, self.algoritmo).unwrap(),
+            ["From", "Subject", "To", "Date"]
+                .into_iter()
+                .map(HeaderName::new_from_ascii_str)
+                .collect(),
+            DkimCanonicalization {
+                header: DkimCanonicalizationType::Relaxed,
+                body: DkimCanonicalizationType::Relaxed,
+            },
+        )
+    }
+
+    /// Nome e conteúdo do registro TXT que deve estar publicado no DNS
+    pub fn registro_dns(&self) -> (String, String) {
+        let nome = format!("{}._domainkey.{}", self.seletor, self.dominio);
+
+        let conteudo = match self.algoritmo {
+            DkimSigningAlgorithm::Rsa => format!(
+                "v=DKIM1; k=rsa; p={}",
+                STANDARD.encode(
+                    RsaPublicKey::from(&self.chave_rsa())
+                        .to_public_key_der()
+                        .unwrap()
+                        .as_bytes()
+                )
+            ),
+            DkimSigningAlgorithm::Ed25519 => format!(
+                "v=DKIM1; k=ed25519; p={}",
+                STANDARD.encode(self.chave_ed25519().verifying_key().as_bytes())
+            ),
+        };
+
+        (nome, conteudo)
+    }
+
+    /// Confere a assinatura DKIM de uma mensagem já formatada
+    pub fn verificar(&self, mensagem: &[u8]) -> Result<(), String> {
+        let mensagem = std::str::from_utf8(mensagem).map_err(|e| e.to_string())?;
+        let (cabecalho, corpo) = mensagem
+            .split_once("\r\n\r\n")
+            .ok_or("mensagem sem corpo")?;
+        let campos = separar_campos(cabecalho);
+
+        let assinatura = campos
+            .iter()
+            .find(|c| nome_do_campo(c).eq_ignore_ascii_case("DKIM-Signature"))
+            .ok_or("mensagem sem cabeçalho DKIM-Signature")?;
+        let tags = ler_tags(&assinatura[assinatura.find(':').unwrap() + 1..]);
+        let tag = |nome: &str| {
+            tags.iter()
+                .find(|(t, _)| t == nome)
+                .map(|(_, v)| v.as_str())
+                .ok_or(format!("tag `{nome}` ausente na assinatura"))
+        };
+
+        if tag("c")? != "relaxed/relaxed" {
+            return Err(format!("canonicalização `{}` não suportada", tag("c")?));
+        }
+        if tag("d")? != self.dominio || tag("s")? != self.seletor {
+            return Err("domínio ou seletor da assinatura não conferem".to_owned());
+        }
+
+        let bh = STANDARD.encode(Sha256::digest(corpo_relaxed(corpo)));
+        if tag("bh")? != bh {
+            return Err("o hash do corpo (bh=) não confere".to_owned());
+        }
+
+        let mut assinado = String::new();
+        for nome in tag("h")?.split(':') {
+            if let Some(c) = campos
+                .iter()
+                .rev()
+                .find(|c| nome_do_campo(c).eq_ignore_ascii_case(nome.trim()))
+            {
+                assinado.push_str(&cabecalho_relaxed(c));
+                assinado.push_str("\r\n");
+            }
+        }
+        assinado.push_str(&cabecalho_relaxed(&remover_valor_b(assinatura)));
+        let hash = Sha256::digest(assinado.as_bytes());
+
+        let b = STANDARD.decode(tag("b")?).map_err(|e| e.to_string())?;
+        match self.algoritmo {
+            DkimSigningAlgorithm::Rsa => RsaPublicKey::from(&self.chave_rsa())
+                .verify(Pkcs1v15Sign::new::<Sha256>(), &hash, &b)
+                .map_err(|e| format!("assinatura inválida: {e}")),
+            DkimSigningAlgorithm::Ed25519 => {
+                let b = ed25519_dalek::Signature::from_slice(&b).map_err(|e| e.to_string())?;
+                self.chave_ed25519()
+                    .verifying_key()
+                    .verify_strict(&hash, &b)
+                    .map_err(|e| format!("assinatura inválida: {e}"))
+            }
+        }
+    }
+
+    fn chave_rsa(&self) -> RsaPrivateKey {
+        RsaPrivateKey::from_pkcs1_pem(&self.chave_privada).unwrap()
+    }
+
+    fn chave_ed25519(&self) -> ed25519_dalek::SigningKey {
+        let bytes = STANDARD.decode(&self.chave_privada).unwrap();
+        ed25519_dalek::SigningKey::from_bytes(&bytes.try_into().unwrap())
+    }
+}
+
+/// Separa o cabeçalho em campos, mantendo as linhas dobradas e o CRLF final
+fn separar_campos(cabecalho: &str) -> Vec<String> {
+    let mut campos: Vec<String> = vec![];
+
+    for linha in cabecalho.split("\r\n") {
+        if linha.starts_with([' ', '\t']) && !campos.is_empty() {
+            campos.last_mut().unwrap().push_str(linha);
+        } else {
+            campos.push(linha.to_owned());
+        }
+        campos.last_mut().unwrap().push_str("\r\n");
+    }
+
+    campos
+}
+
+fn nome_do_campo(campo: &str) -> &str {
+    campo.split(':').next().unwrap().trim()
+}
+
+fn ler_tags(valor: &str) -> Vec<(String, String)> {
+    valor
+        .split(';')
+        .filter_map(|t| t.split_once('='))
+        .map(|(t, v)| {
+            (
+                t.trim().to_owned(),
+                v.chars().filter(|c| !c.is_whitespace()).collect(),
+            )
+        })
+        .collect()
+}
+
+/// O campo DKIM-Signature como foi assinado: igual, mas com `b=` vazio
+fn remover_valor_b(campo: &str) -> String {
+    let mut inicio = None;
+
+    for (i, _) in campo.match_indices(';') {
+        let resto = &campo[i + 1..];
+        let tag = resto.trim_start_matches([' ', '\t', '\r', '\n']);
+        if tag.starts_with("b=") {
+            inicio = Some(i + 1 + (resto.len() - tag.len()) + 2);
+        }
+    }
+
+    match inicio {
+        Some(i) => {
+            let fim = campo[i..]
+                .find(';')
+                .map(|f| i + f)
+                .unwrap_or(campo.trim_end_matches("\r\n").len());
+            format!("{}{}", &campo[..i], &campo[fim..])
+        }
+        None => campo.to_owned(),
+    }
+}
+
+/// Canonicalização `relaxed` de um campo do cabeçalho (RFC 6376, 3.4.2), sem
+/// o CRLF final
+fn cabecalho_relaxed(campo: &str) -> String {
+    let (nome, valor) = campo.split_once(':').unwrap();
+    let valor = reduzir_espacos(&valor.replace("\r\n", ""));
+
+    format!("{}:{}", nome.trim().to_lowercase(), valor.trim())
+}
+
+/// Troca cada sequência de espaços e tabs por um único espaço
+fn reduzir_espacos(linha: &str) -> String {
+    let mut reduzida = String::new();
+
+    for c in linha.chars() {
+        if c == ' ' || c == '\t' {
+            if !reduzida.ends_with(' ') {
+                reduzida.push(' ');
+            }
+        } else {
+            reduzida.push(c);
+        }
+    }
+
+    reduzida
+}
+
+/// Canonicalização `relaxed` do corpo (RFC 6376, 3.4.4)
+fn corpo_relaxed(corpo: &str) -> String {
+    let mut saida = String::new();
+
+    for linha in corpo.split("\r\n") {
+        saida.push_str(reduzir_espacos(linha).trim_end_matches(' '));
+        saida.push_str("\r\n");
+    }
+
+    while saida.ends_with("\r\n\r\n") {
+        saida.truncate(saida.len() - 2);
+    }
+    if saida == "\r\n" {
+        saida.clear();
+    }
+
+    saida
+}
+
+#[cfg(test)]
+mod tests {
+    use lettre::{message::Mailbox, Message};
+    use rsa::pkcs1::{EncodeRsaPrivateKey, LineEnding};
+
+    use super::*;
+
+    fn dkim_ed25519() -> Dkim {
+        Dkim {
+            seletor: "amigo".to_owned(),
+            dominio: "example.com".to_owned(),
+            algoritmo: DkimSigningAlgorithm::Ed25519,
+            chave_privada: STANDARD.encode([7u8; 32]),
+        }
+    }
+
+    fn dkim_rsa() -> Dkim {
+        let chave = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+
+        Dkim {
+            seletor: "amigo".to_owned(),
+            dominio: "example.com".to_owned(),
+            algoritmo: DkimSigningAlgorithm::Rsa,
+            chave_privada: chave.to_pkcs1_pem(LineEnding::LF).unwrap().to_string(),
+        }
+    }
+
+    fn assinada(dkim: &Dkim, corpo: &str) -> String {
+        let mut message = Message::builder()
+            .from("Organizador <org@example.com>".parse::<Mailbox>().unwrap())
+            .to("Ana <ana@example.com>".parse::<Mailbox>().unwrap())
+            .subject("Amigo  Secreto")
+            .body(corpo.to_owned())
+            .unwrap();
+        message.sign(&dkim.config());
+
+        String::from_utf8(message.formatted()).unwrap()
+    }
+
+    #[test]
+    fn assinatura_ed25519_confere() {
+        let dkim = dkim_ed25519();
+        let mensagem = assinada(&dkim, "Ana, seu amigo secreto é Beto  \r\n\r\n");
+
+        assert_eq!(dkim.verificar(mensagem.as_bytes()), Ok(()));
+    }
+
+    #[test]
+    fn assinatura_rsa_confere() {
+        let dkim = dkim_rsa();
+        let mensagem = assinada(&dkim, "Ana, seu amigo secreto é Beto");
+
+        assert_eq!(dkim.verificar(mensagem.as_bytes()), Ok(()));
+    }
+
+    #[test]
+    fn corpo_alterado_nao_confere() {
+        let dkim = dkim_ed25519();
+        let mensagem = assinada(&dkim, "Ana, seu amigo secreto é Beto").replace("Beto", "Caio");
+
+        assert_eq!(
+            dkim.verificar(mensagem.as_bytes()),
+            Err("o hash do corpo (bh=) não confere".to_owned())
+        );
+    }
+
+    #[test]
+    fn cabecalho_alterado_nao_confere() {
+        let dkim = dkim_ed25519();
+        let mensagem = assinada(&dkim, "Ana, seu amigo secreto é Beto")
+            .replace("Subject: Amigo  Secreto", "Subject: Outro assunto");
+
+        assert!(dkim
+            .verificar(mensagem.as_bytes())
+            .unwrap_err()
+            .starts_with("assinatura inválida"));
+    }
+
+    #[test]
+    fn outra_chave_nao_confere() {
+        let mensagem = assinada(&dkim_ed25519(), "Ana, seu amigo secreto é Beto");
+        let outra = Dkim {
+            chave_privada: STANDARD.encode([8u8; 32]),
+            ..dkim_ed25519()
+        };
+
+        assert!(outra.verificar(mensagem.as_bytes()).is_err());
+    }
+
+    /// Exemplo da RFC 6376, 3.4.5
+    #[test]
+    fn cabecalho_relaxed_da_rfc() {
+        let campos = separar_campos("A: X\r\nB : Y\t\r\n\tZ  ");
+
+        assert_eq!(campos, ["A: X\r\n", "B : Y\t\r\n\tZ  \r\n"]);
+        assert_eq!(cabecalho_relaxed(&campos[0]), "a:X");
+        assert_eq!(cabecalho_relaxed(&campos[1]), "b:Y Z");
+    }
+
+    /// Exemplo da RFC 6376, 3.4.5
+    #[test]
+    fn corpo_relaxed_da_rfc() {
+        assert_eq!(corpo_relaxed(" C \r\nD \t E\r\n\r\n\r\n"), " C\r\nD E\r\n");
+    }
+
+    /// RFC 6376, 3.4.4: um corpo vazio continua vazio
+    #[test]
+    fn corpo_relaxed_vazio() {
+        assert_eq!(corpo_relaxed(""), "");
+        assert_eq!(corpo_relaxed("\r\n\r\n"), "");
+    }
+
+    #[test]
+    fn valor_b_removido() {
+        let campo = "DKIM-Signature: v=1; a=ed25519-sha256; b=abc\r\n\tdef; bh=xyz\r\n";
+
+        assert_eq!(
+            remover_valor_b(campo),
+            "DKIM-Signature: v=1; a=ed25519-sha256; b=; bh=xyz\r\n"
+        );
+    }
+}
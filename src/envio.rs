@@ -1,14 +1,25 @@
+use std::{
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Days, Local, NaiveDate, Utc};
+use futures::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
 use lettre::{
-    transport::smtp::{authentication::Credentials, client::TlsParameters},
-    Message, SmtpTransport, Transport,
+    address::Envelope,
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, client::TlsParameters, PoolConfig},
+    AsyncSmtpTransport, AsyncTransport, Message, SmtpTransport, Tokio1Executor,
 };
-use rand::seq::SliceRandom;
+use rand::{distributions::Alphanumeric, seq::SliceRandom, Rng};
 use rand_chacha::ChaCha20Rng;
 use rusqlite::{params, Connection};
 
 use crate::{
     config::Config,
-    db::{Jogador, Sorteio},
+    cripto::{Chave, Chaveiro},
+    db::{ItemOutbox, Jogador, Sorteio, TipoEnvio},
 };
 
 pub fn make_transport(ctx: &crate::config::Config) -> SmtpTransport {
@@ -25,28 +36,182 @@ pub fn make_transport(ctx: &crate::config::Config) -> SmtpTransport {
             tls_param,
         ))
         .credentials(credentials)
+        .pool_config(PoolConfig::new().max_size(ctx.smtp_max_connections))
         .build()
 }
 
+/// Transporte assíncrono usado nos envios de verdade, com até
+/// `smtp_max_connections` conexões abertas ao mesmo tempo
+pub fn make_async_transport(ctx: &crate::config::Config) -> AsyncSmtpTransport<Tokio1Executor> {
+    let credentials = Credentials::new(ctx.smtp_username.clone(), ctx.smtp_password.clone());
+
+    let tls_param = TlsParameters::builder(ctx.smtp_relay.clone())
+        .build()
+        .unwrap();
+
+    AsyncSmtpTransport::<Tokio1Executor>::relay(&ctx.smtp_relay)
+        .unwrap()
+        .port(ctx.smtp_port)
+        .tls(lettre::transport::smtp::client::Tls::Opportunistic(
+            tls_param,
+        ))
+        .credentials(credentials)
+        .pool_config(PoolConfig::new().max_size(ctx.smtp_max_connections))
+        .build()
+}
+
+/// Transporte sem credenciais e com STARTTLS obrigatório, usado pelo
+/// `smtp test` para separar a etapa de TLS da de autenticação
+pub fn make_transport_tls_only(ctx: &crate::config::Config) -> SmtpTransport {
+    let tls_param = TlsParameters::builder(ctx.smtp_relay.clone())
+        .build()
+        .unwrap();
+
+    SmtpTransport::relay(&ctx.smtp_relay)
+        .unwrap()
+        .port(ctx.smtp_port)
+        .tls(lettre::transport::smtp::client::Tls::Required(tls_param))
+        .build()
+}
+
+/// Espaça os envios para respeitar `smtp_messages_per_minute`, mesmo com
+/// várias mensagens saindo em paralelo
+pub struct Limitador {
+    intervalo: Option<Duration>,
+    proximo: Mutex<Option<Instant>>,
+}
+
+impl Limitador {
+    pub fn new(ctx: &Config) -> Limitador {
+        Limitador {
+            intervalo: ctx
+                .smtp_messages_per_minute
+                .map(|m| Duration::from_secs(60) / m),
+            proximo: Mutex::new(None),
+        }
+    }
+
+    /// O limitador do processo, compartilhado pelos envios e pelos avisos ao
+    /// organizador para que o intervalo valha entre uma rodada e outra
+    pub fn global(ctx: &Config) -> &'static Limitador {
+        static LIMITADOR: OnceLock<Limitador> = OnceLock::new();
+        LIMITADOR.get_or_init(|| Limitador::new(ctx))
+    }
+
+    /// Reserva o próximo horário livre e espera até ele
+    pub async fn esperar(&self) {
+        let Some(intervalo) = self.intervalo else {
+            return;
+        };
+
+        let horario = {
+            let mut proximo = self.proximo.lock().unwrap();
+            let horario = proximo.unwrap_or(Instant::now()).max(Instant::now());
+            *proximo = Some(horario + intervalo);
+            horario
+        };
+
+        tokio::time::sleep_until(horario.into()).await;
+    }
+}
+
+/// Como o servidor classificou uma falha de envio
+pub enum TipoErro {
+    /// Resposta 4xx: vale a pena tentar de novo
+    Temporario,
+    /// Resposta 5xx: tentar de novo não adianta
+    Permanente,
+    /// Falhas de rede, TLS, etc., sem código SMTP
+    Outro,
+}
+
+pub trait ClassificaErro: ToString {
+    fn tipo(&self) -> TipoErro;
+}
+
+impl ClassificaErro for lettre::transport::smtp::Error {
+    fn tipo(&self) -> TipoErro {
+        if self.is_transient() {
+            TipoErro::Temporario
+        } else if self.is_permanent() {
+            TipoErro::Permanente
+        } else {
+            TipoErro::Outro
+        }
+    }
+}
+
+/// O que vai no corpo da mensagem
+#[derive(Debug, Clone)]
+pub enum Conteudo {
+    /// O resultado do sorteio
+    Sorteio,
+    /// Lembrete de quem o destino tirou, para o evento no dia dado
+    Lembrete(NaiveDate),
+    /// A corrente inteira, como pares (quem tirou, quem foi tirado)
+    Revelacao(Vec<(Jogador, Jogador)>),
+}
+
 #[derive(Debug, Clone)]
 pub struct ProcessoEnvio {
     pub destino: Jogador,
     pub sorteado: Jogador,
     pub sorteio: u64,
+    pub conteudo: Conteudo,
 }
 
 impl ProcessoEnvio {
-    pub fn enviar<T: Transport>(
-        self,
-        sender: T,
-        conn: &mut Connection,
+    pub fn tipo(&self) -> TipoEnvio {
+        match self.conteudo {
+            Conteudo::Sorteio => TipoEnvio::Sorteio,
+            Conteudo::Lembrete(_) => TipoEnvio::Lembrete,
+            Conteudo::Revelacao(_) => TipoEnvio::Revelacao,
+        }
+    }
+
+    /// Monta a mensagem para o destino, cifrada com PGP se ele tiver uma chave
+    /// e assinada com DKIM se configurado. Sem `data`, vale a hora atual.
+    ///
+    /// Cada mensagem ganha um `Message-ID` próprio, que os bounces citam e que
+    /// `envio bounces` usa para achar o envio devolvido. Com `token` e
+    /// `PUBLIC_URL`, o corpo termina com o link de confirmação do `serve`
+    pub fn mensagem(
+        &self,
         ctx: &crate::config::Config,
-    ) -> usize
-    where
-        T::Error: ToString,
-    {
-        let message = Message::builder()
-            .from(ctx.smtp_sender.parse().unwrap())
+        data: Option<DateTime<Utc>>,
+        token: Option<&str>,
+    ) -> Result<Message, String> {
+        let (subject, mut corpo) = match &self.conteudo {
+            Conteudo::Sorteio => (&ctx.subject, (ctx.format_message)(self.clone())),
+            Conteudo::Lembrete(data) => (
+                &ctx.subject_lembrete,
+                (ctx.format_lembrete)(self.clone(), *data),
+            ),
+            Conteudo::Revelacao(pares) => (
+                &ctx.subject_revelacao,
+                (ctx.format_revelacao)(self.clone(), pares.clone()),
+            ),
+        };
+
+        if let (Some(url), Some(token)) = (&ctx.url_publica, token) {
+            corpo.push_str(&format!(
+                "\n\nConfirme que recebeu esta mensagem: {}/confirmar/{}",
+                url.trim_end_matches('/'),
+                token
+            ));
+        }
+
+        let remetente: Mailbox = ctx.smtp_sender.parse().unwrap();
+        let message_id = format!(
+            "<amigo.{}.{}@{}>",
+            self.sorteio,
+            aleatorio(20),
+            remetente.email.domain()
+        );
+
+        let mut builder = Message::builder()
+            .from(remetente)
+            .message_id(Some(message_id))
             .to(format!(
                 "{} <{}>",
                 self.destino.nome.clone(),
@@ -54,106 +219,643 @@ impl ProcessoEnvio {
             )
             .parse()
             .unwrap())
-            .subject(ctx.subject.clone())
-            .body((ctx.format_message)(self.clone()))
-            .unwrap();
+            .subject(subject.clone());
+        if let Some(data) = data {
+            builder = builder.date(data.into());
+        }
+
+        let mut message = match &self.destino.pgp_public_key {
+            Some(pgp) => builder
+                .multipart(
+                    crate::openpgp::cifrar_corpo(pgp, corpo)
+                        .map_err(|e| format!("Falha ao cifrar com PGP: {e}"))?,
+                )
+                .unwrap(),
+            None => builder.body(corpo).unwrap(),
+        };
+
+        if let Some(dkim) = &ctx.dkim {
+            message.sign(&dkim.config());
+        }
+
+        Ok(message)
+    }
+}
+
+/// Texto alfanumérico aleatório, para Message-IDs e tokens de confirmação
+pub(crate) fn aleatorio(tamanho: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(tamanho)
+        .map(char::from)
+        .collect()
+}
+
+/// Maior espera entre duas passagens do `worker` pelo mesmo item
+const ESPERA_MAXIMA: Duration = Duration::from_secs(60 * 60);
+
+/// Monta as mensagens e as coloca na `outbox`, numa única transação.
+///
+/// A mensagem montada contém o sorteado em claro, então vai para a base cifrada
+/// com a chave do sorteio. Mensagens que nem chegam a ser montadas (uma chave
+/// PGP inválida, por exemplo) viram envios com erro na hora. Com
+/// `agendado_para`, os itens só ficam disponíveis para o `worker` a partir dele.
+/// Com `PUBLIC_URL`, os resultados do sorteio levam um token de confirmação.
+///
+/// Retorna os ids dos itens enfileirados e dos envios com erro
+pub fn enfileirar(
+    processos: Vec<ProcessoEnvio>,
+    ctx: &Config,
+    conn: &mut Connection,
+    chave: &Chave,
+    agendado_para: Option<DateTime<Utc>>,
+) -> (Vec<u64>, Vec<usize>) {
+    let disponivel_em = agendado_para.map(|d| d.format(crate::db::FORMATO_DATA).to_string());
+    let tx = conn.transaction().unwrap();
+    let mut itens = vec![];
+    let mut erros = vec![];
+
+    for processo in processos {
+        let sorteado_cifrado = chave.cifrar_sorteado(processo.sorteado.id);
+        let token = (ctx.url_publica.is_some() && processo.tipo() == TipoEnvio::Sorteio)
+            .then(|| aleatorio(32));
+
+        match processo.mensagem(ctx, agendado_para, token.as_deref()) {
+            Ok(m) => {
+                let envelope = m.envelope();
+                let id = crate::db::outbox::create_item(
+                    &tx,
+                    processo.sorteio,
+                    processo.destino.id,
+                    sorteado_cifrado,
+                    envelope.from().unwrap().to_string(),
+                    envelope.to()[0].to_string(),
+                    chave.cifrar(&m.formatted()),
+                    disponivel_em.as_ref(),
+                    processo.tipo(),
+                    m.headers().get_raw("Message-ID"),
+                    token.as_deref(),
+                );
+                itens.push(id as u64);
+            }
+            Err(e) => erros.push(register_error(
+                &tx,
+                processo.sorteio,
+                processo.destino.id,
+                sorteado_cifrado,
+                processo.tipo(),
+                None,
+                None,
+                e,
+            )),
+        }
+    }
+
+    tx.commit().unwrap();
+    (itens, erros)
+}
+
+/// Faz uma passagem pela `outbox`, entregando em paralelo (no máximo
+/// `smtp_max_connections` ao mesmo tempo) os itens cuja vez já chegou.
+///
+/// Cada resultado é gravado assim que chega: o item entregue, ou que falhou
+/// de vez, vira um envio e sai da fila na mesma transação, então uma queda no
+/// meio da passagem não faz ninguém receber a mensagem duas vezes. Os demais
+/// voltam para a fila com espera exponencial.
+///
+/// Com `apenas`, só passa por esses itens, para que um comando entregue o que
+/// ele mesmo enfileirou sem mexer no que outros sorteios deixaram na fila
+/// (e que podem estar cifrados com outra senha); a fila inteira é só do
+/// `worker`. Itens de sorteios que a senha não abre (vindos de outra base,
+/// por exemplo) ficam na fila, com um aviso.
+///
+/// Retorna, para cada item processado, o id do envio criado, ou `None` se ele
+/// continua na fila
+pub fn drenar(
+    ctx: &Config,
+    conn: &mut Connection,
+    chaveiro: &mut Chaveiro,
+    apenas: Option<&[u64]>,
+) -> Vec<(u64, Option<usize>)> {
+    let ja_recusados = chaveiro.recusados().clone();
+    let itens = crate::db::outbox::get_itens_prontos(conn)
+        .into_iter()
+        .filter(|item| apenas.is_none_or(|ids| ids.contains(&item.id)))
+        .filter(|item| chaveiro.abre(conn, item.sorteio))
+        .collect::<Vec<_>>();
+
+    let novos = chaveiro
+        .recusados()
+        .difference(&ja_recusados)
+        .collect::<Vec<_>>();
+    if !novos.is_empty() {
+        tracing::warn!(
+            "A senha não abre os sorteios {:?}; os itens deles continuam na fila",
+            novos
+        );
+    }
+
+    let itens = itens
+        .into_iter()
+        .map(|item| {
+            let mensagem = chaveiro
+                .chave(conn, item.sorteio)
+                .decifrar(&item.mensagem_cifrada)
+                .unwrap_or_else(|| panic!("Não foi possível decifrar o item {} da fila", item.id));
+            (item, mensagem)
+        })
+        .collect::<Vec<_>>();
+
+    if itens.is_empty() {
+        return vec![];
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
 
-        let result = sender.send(&message);
+    runtime.block_on(async {
+        let transport = make_async_transport(ctx);
+        let limitador = Limitador::global(ctx);
+        let barra = ProgressBar::new(itens.len() as u64).with_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} envios ({eta})").unwrap(),
+        );
 
-        match result {
-            Ok(_) => register_success(&self, conn),
-            Err(x) => register_error(&self, conn, x.to_string()),
+        let mut resultados = futures::stream::iter(itens)
+            .map(|(item, mensagem)| {
+                let transport = &transport;
+                async move {
+                    let envelope = Envelope::new(
+                        Some(item.remetente.parse().unwrap()),
+                        vec![item.destinatario.parse().unwrap()],
+                    )
+                    .unwrap();
+                    let resultado =
+                        send_with_retries(transport, &envelope, &mensagem, ctx, limitador).await;
+                    (item, resultado)
+                }
+            })
+            .buffer_unordered(ctx.smtp_max_connections.max(1) as usize);
+
+        let mut entregas = vec![];
+        while let Some((item, resultado)) = resultados.next().await {
+            barra.inc(1);
+            entregas.push(registrar(conn, ctx, item, resultado));
+        }
+
+        barra.finish_and_clear();
+        entregas
+    })
+}
+
+/// Envia a mensagem, tentando de novo com espera exponencial enquanto o
+/// servidor responder com erros temporários
+async fn send_with_retries(
+    transport: &AsyncSmtpTransport<Tokio1Executor>,
+    envelope: &Envelope,
+    mensagem: &[u8],
+    ctx: &Config,
+    limitador: &Limitador,
+) -> Result<(), (TipoErro, String)> {
+    let mut tentativa = 0;
+
+    loop {
+        limitador.esperar().await;
+
+        let erro = match transport.send_raw(envelope, mensagem).await {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        match erro.tipo() {
+            TipoErro::Temporario if tentativa < ctx.smtp_max_retries => {
+                let espera = (ctx.smtp_backoff * 2u32.pow(tentativa.min(16))).min(ESPERA_MAXIMA);
+                tentativa += 1;
+                tracing::warn!("Erro temporário ({erro}), tentando de novo em {espera:?}");
+                tokio::time::sleep(espera).await;
+            }
+            TipoErro::Temporario => {
+                return Err((
+                    TipoErro::Temporario,
+                    format!("temporário, após {} tentativas: {erro}", tentativa + 1),
+                ))
+            }
+            TipoErro::Permanente => {
+                return Err((TipoErro::Permanente, format!("permanente: {erro}")))
+            }
+            TipoErro::Outro => return Err((TipoErro::Outro, erro.to_string())),
         }
     }
 }
 
-fn register_success(processo: &ProcessoEnvio, conn: &mut Connection) -> usize {
+/// Envia mensagens que não passam pela fila (os avisos ao organizador) pelo
+/// mesmo caminho dos envios: transporte assíncrono, limite de mensagens por
+/// minuto e novas tentativas em erros temporários
+pub fn enviar_avulsas(ctx: &Config, mensagens: Vec<Message>) -> Vec<Result<(), String>> {
+    if mensagens.is_empty() {
+        return vec![];
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let transport = make_async_transport(ctx);
+        let limitador = Limitador::global(ctx);
+
+        let mut resultados = vec![];
+        for message in mensagens {
+            let resultado = send_with_retries(
+                &transport,
+                message.envelope(),
+                &message.formatted(),
+                ctx,
+                limitador,
+            )
+            .await;
+            resultados.push(resultado.map_err(|(_, e)| e));
+        }
+        resultados
+    })
+}
+
+/// Grava o resultado de um item numa transação só dele.
+///
+/// Os resultados não são agrupados em lotes de propósito: num lote ainda não
+/// gravado quando o processo cai, as mensagens já entregues continuariam na
+/// fila e sairiam de novo na próxima passagem. Como cada item sai da fila na
+/// mesma transação que cria o seu envio, um commit por mensagem garante que
+/// ninguém recebe duas vezes, e custa pouco perto do próprio envio SMTP
+fn registrar(
+    conn: &mut Connection,
+    ctx: &Config,
+    item: ItemOutbox,
+    resultado: Result<(), (TipoErro, String)>,
+) -> (u64, Option<usize>) {
+    let tx = conn.transaction().unwrap();
+
+    let envio = match resultado {
+        Ok(()) => Some(register_success(
+            &tx,
+            item.sorteio,
+            item.destino,
+            &item.sorteado_cifrado,
+            item.tipo,
+            item.message_id.as_deref(),
+            item.token.as_deref(),
+        )),
+        Err((TipoErro::Permanente, e)) => Some(register_error(
+            &tx,
+            item.sorteio,
+            item.destino,
+            item.sorteado_cifrado.clone(),
+            item.tipo,
+            item.message_id.as_deref(),
+            item.token.as_deref(),
+            e,
+        )),
+        Err((_, e)) if item.tentativas + 1 >= ctx.outbox_max_attempts => Some(register_error(
+            &tx,
+            item.sorteio,
+            item.destino,
+            item.sorteado_cifrado.clone(),
+            item.tipo,
+            item.message_id.as_deref(),
+            item.token.as_deref(),
+            format!("desistindo após {} passagens: {e}", item.tentativas + 1),
+        )),
+        Err((_, e)) => {
+            let espera = (ctx.smtp_backoff * 2u32.pow(item.tentativas.min(16)))
+                .min(ESPERA_MAXIMA)
+                .as_secs()
+                .max(1);
+            tracing::warn!(
+                "Envio para id {} volta para a fila, nova tentativa em {espera}s: {e}",
+                item.destino
+            );
+            crate::db::outbox::reagendar(&tx, item.id, &e, espera);
+            None
+        }
+    };
+
+    if envio.is_some() {
+        crate::db::outbox::delete_item_by_id(&tx, item.id);
+    }
+
+    tx.commit().unwrap();
+    (item.id, envio)
+}
+
+fn register_success(
+    conn: &Connection,
+    sorteio: u64,
+    destino: u64,
+    sorteado_cifrado: &[u8],
+    tipo: TipoEnvio,
+    message_id: Option<&str>,
+    token: Option<&str>,
+) -> usize {
     let mut query = conn
-        .prepare("INSERT INTO envios (sorteio, destino, sorteado, sucesso) VALUES (?1, ?2, ?3, ?4) RETURNING id")
+        .prepare("INSERT INTO envios (sorteio, destino, sorteado_cifrado, sucesso, tipo, message_id, token) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) RETURNING id")
         .unwrap();
 
     query
         .query_row(
             params![
-                processo.sorteio,
-                processo.destino.id,
-                processo.sorteado.id,
-                true
+                sorteio,
+                destino,
+                sorteado_cifrado,
+                true,
+                tipo,
+                message_id,
+                token
             ],
             |x| Ok(x.get(0).unwrap()),
         )
         .unwrap()
 }
 
-#[tracing::instrument(level = "debug")]
-fn register_error(processo: &ProcessoEnvio, conn: &mut Connection, error: String) -> usize {
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(level = "debug", skip(conn, sorteado_cifrado, token))]
+fn register_error(
+    conn: &Connection,
+    sorteio: u64,
+    destino: u64,
+    sorteado_cifrado: Vec<u8>,
+    tipo: TipoEnvio,
+    message_id: Option<&str>,
+    token: Option<&str>,
+    error: String,
+) -> usize {
     let mut query = conn
-        .prepare("INSERT INTO envios (sorteio, destino, sorteado, sucesso, erro) VALUES (?1, ?2, ?3, ?4, ?5) RETURNING id")
+        .prepare("INSERT INTO envios (sorteio, destino, sorteado_cifrado, sucesso, erro, tipo, message_id, token) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) RETURNING id")
         .unwrap();
 
     query
         .query_row(
             params![
-                processo.sorteio,
-                processo.destino.id,
-                processo.sorteado.id,
+                sorteio,
+                destino,
+                sorteado_cifrado,
                 false,
-                error
+                error,
+                tipo,
+                message_id,
+                token
             ],
             |x| Ok(x.get(0).unwrap()),
         )
         .unwrap()
 }
 
-/// Realmente roda o sorteio, enviando os emails.
+/// Quantas vezes o sorteio embaralha de novo procurando uma corrente que
+/// respeite os grupos e as exclusões
+const TENTATIVAS_SORTEIO: usize = 10_000;
+
+/// O gerador determinado pela semente do sorteio, usado tanto para a corrente
+/// quanto para a ordem dos envios
+pub fn gerador(seed: &str) -> ChaCha20Rng {
+    rand_seeder::Seeder::from(seed).make_rng()
+}
+
+/// Sorteia a corrente: embaralha os jogadores com o gerador da semente e cada
+/// um tira o seguinte.
+///
+/// Jogadores do mesmo grupo não podem se tirar, nem alguém tirar quem ele
+/// exclui; enquanto a corrente não respeitar isso, embaralha de novo com o
+/// mesmo gerador, então a semente continua determinando o resultado. Sem
+/// grupos nem exclusões, vale o primeiro embaralhamento
+pub fn sortear(
+    rand: &mut ChaCha20Rng,
+    mut jogadores: Vec<Jogador>,
+    exclusoes: &[(u64, u64)],
+) -> Result<Vec<(Jogador, Jogador)>, String> {
+    let permitido = |destino: &Jogador, sorteado: &Jogador| {
+        let mesmo_grupo = destino.grupo.is_some() && destino.grupo == sorteado.grupo;
+        !mesmo_grupo && !exclusoes.contains(&(destino.id, sorteado.id))
+    };
+
+    for _ in 0..TENTATIVAS_SORTEIO {
+        jogadores.shuffle(rand);
+
+        let pares = jogadores
+            .iter()
+            .enumerate()
+            .map(|(idx, j)| (j.clone(), jogadores[(idx + 1) % jogadores.len()].clone()))
+            .collect::<Vec<_>>();
+
+        if pares.iter().all(|(d, s)| permitido(d, s)) {
+            return Ok(pares);
+        }
+    }
+
+    Err(format!(
+        "nenhuma corrente respeitou os grupos e as exclusões do jogo em {TENTATIVAS_SORTEIO} tentativas"
+    ))
+}
+
+/// Realmente roda o sorteio: coloca as mensagens da corrente já sorteada na
+/// fila e faz uma passagem por ela.
 ///
-/// É o núcleo de todo o funcionamento
+/// É o núcleo de todo o funcionamento. Retorna os ids dos envios já
+/// registrados; o que ficar na fila (inclusive tudo, se o sorteio estiver
+/// agendado) é entregue depois pelo `worker`
 pub fn run_and_email(
     sorteio: Sorteio,
-    mut jogadores: Vec<Jogador>,
+    pares: Vec<(Jogador, Jogador)>,
+    rand: &mut ChaCha20Rng,
     smtp_ctx: &Config,
     conn: &mut Connection,
+    chaveiro: &mut Chaveiro,
+    agendado_para: Option<DateTime<Utc>>,
 ) -> Vec<usize> {
-    // shuffle jogadores according to seed
-    let mut rand: ChaCha20Rng = rand_seeder::Seeder::from(sorteio.seed.clone()).make_rng();
-    jogadores.shuffle(&mut rand);
+    let mut processos = pares
+        .into_iter()
+        .map(|(destino, sorteado)| ProcessoEnvio {
+            destino,
+            sorteado,
+            sorteio: sorteio.id,
+            conteudo: Conteudo::Sorteio,
+        })
+        .collect::<Vec<_>>();
 
-    let transport = make_transport(&smtp_ctx);
+    // a ordem de envio (e dos ids em `envios` e `outbox`) não pode seguir a corrente
+    processos.shuffle(rand);
 
-    let mut results = iter_and_send(jogadores, sorteio.clone(), transport, conn, smtp_ctx);
+    let chave = chaveiro.chave(conn, sorteio.id);
+    let (itens, mut results) = enfileirar(processos, smtp_ctx, conn, &chave, agendado_para);
+    results.extend(
+        drenar(smtp_ctx, conn, chaveiro, Some(&itens))
+            .into_iter()
+            .filter_map(|(_, envio)| envio),
+    );
 
-    results.shuffle(&mut rand); // so the cli printing order does not reflect actual emails;
+    results.shuffle(rand); // so the cli printing order does not reflect actual emails;
     results
 }
 
-fn iter_and_send(
-    jogadores: Vec<Jogador>,
-    sorteio: Sorteio,
-    transport: lettre::SmtpTransport,
-    conn: &mut Connection,
-    smtp_ctx: &Config,
-) -> Vec<usize> {
-    let mut results = vec![];
+/// Coloca na fila os lembretes dos jogos cujo evento está a até
+/// `lembrete_dias` dias, para quem recebeu o resultado e ainda não foi lembrado.
+///
+/// O sorteado vem do envio já guardado, sem rodar o sorteio de novo. Retorna
+/// quantos lembretes foram enfileirados
+pub fn agendar_lembretes(ctx: &Config, conn: &mut Connection, chaveiro: &mut Chaveiro) -> usize {
+    let hoje = Local::now().date_naive();
+    let mut total = 0;
 
-    for (idx, j) in jogadores.iter().enumerate() {
-        let processo = {
-            if idx == (jogadores.len() - 1) {
-                ProcessoEnvio {
-                    destino: j.clone(),
-                    sorteado: jogadores[0].clone(),
-                    sorteio: sorteio.id,
-                }
-            } else {
+    for jogo in crate::db::jogo::get_jogos_com_evento(conn) {
+        let data =
+            NaiveDate::parse_from_str(jogo.data_evento.as_ref().unwrap(), "%Y-%m-%d").unwrap();
+        if hoje > data || hoje + Days::new(ctx.lembrete_dias.into()) < data {
+            continue;
+        }
+
+        let Some(sorteio) = crate::db::sorteio::get_ultimo_sorteio_enviado(conn, jogo.id) else {
+            continue;
+        };
+
+        let envios = crate::db::envios::get_envios_sem_lembrete(conn, sorteio.id);
+        if envios.is_empty() {
+            continue;
+        }
+
+        let chave = chaveiro.chave(conn, sorteio.id);
+        let mut processos = envios
+            .iter()
+            .map(|e| {
+                let sorteado = chaveiro.sorteado(conn, e);
                 ProcessoEnvio {
-                    destino: j.clone(),
-                    sorteado: jogadores[idx + 1].clone(),
+                    destino: crate::db::jogador::get_jogador_by_id(conn, e.destino),
+                    sorteado: crate::db::jogador::get_jogador_by_id(conn, sorteado),
                     sorteio: sorteio.id,
+                    conteudo: Conteudo::Lembrete(data),
                 }
+            })
+            .collect::<Vec<_>>();
+        processos.shuffle(&mut rand::thread_rng());
+
+        let (itens, erros) = enfileirar(processos, ctx, conn, &chave, None);
+        tracing::info!(
+            "Enfileirados {} lembretes do jogo {} ({} com erro)",
+            itens.len(),
+            jogo.id,
+            erros.len()
+        );
+        total += itens.len();
+    }
+
+    total
+}
+
+/// A corrente do sorteio, refeita a partir dos sorteados guardados nos envios
+/// de resultado, começando pelo primeiro destino e seguindo quem cada um tirou
+pub fn corrente(
+    conn: &mut Connection,
+    chaveiro: &mut Chaveiro,
+    sorteio: u64,
+) -> Vec<(Jogador, Jogador)> {
+    let mut pares = std::collections::BTreeMap::new();
+    for e in crate::db::envios::get_envios_by_sorteio(conn, sorteio) {
+        if e.tipo == TipoEnvio::Sorteio {
+            pares.insert(e.destino, chaveiro.sorteado(conn, &e));
+        }
+    }
+
+    let mut ordem = vec![];
+    while let Some((&inicio, _)) = pares.iter().next() {
+        let mut atual = inicio;
+        while let Some(sorteado) = pares.remove(&atual) {
+            ordem.push((atual, sorteado));
+            atual = sorteado;
+        }
+    }
+
+    ordem
+        .into_iter()
+        .map(|(destino, sorteado)| {
+            (
+                crate::db::jogador::get_jogador_by_id(conn, destino),
+                crate::db::jogador::get_jogador_by_id(conn, sorteado),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jogador(id: u64, grupo: Option<&str>) -> Jogador {
+        Jogador {
+            id,
+            nome: format!("Jogador {id}"),
+            email: format!("j{id}@example.com"),
+            jogo: 1,
+            pgp_public_key: None,
+            grupo: grupo.map(str::to_owned),
+            idioma: None,
+            lista_desejos: None,
+        }
+    }
+
+    fn ids(pares: &[(Jogador, Jogador)]) -> Vec<(u64, u64)> {
+        pares.iter().map(|(d, s)| (d.id, s.id)).collect()
+    }
+
+    fn jogadores() -> Vec<Jogador> {
+        vec![
+            jogador(1, Some("silva")),
+            jogador(2, Some("silva")),
+            jogador(3, Some("souza")),
+            jogador(4, Some("souza")),
+            jogador(5, None),
+            jogador(6, None),
+        ]
+    }
+
+    #[test]
+    fn mesma_semente_mesma_corrente() {
+        let exclusoes = [(5, 6)];
+        let a = sortear(&mut gerador("semente"), jogadores(), &exclusoes).unwrap();
+        let b = sortear(&mut gerador("semente"), jogadores(), &exclusoes).unwrap();
+        assert_eq!(ids(&a), ids(&b));
+
+        let sem_restricoes = |seed| ids(&sortear(&mut gerador(seed), jogadores(), &[]).unwrap());
+        assert_ne!(sem_restricoes("semente"), sem_restricoes("outra semente"));
+    }
+
+    #[test]
+    fn respeita_grupos_e_exclusoes() {
+        let exclusoes = [(5, 6), (6, 1)];
+
+        for seed in ["a", "b", "c", "d", "e", "f", "g", "h"] {
+            let pares = sortear(&mut gerador(seed), jogadores(), &exclusoes).unwrap();
+
+            // uma corrente só, em que todos tiram e são tirados uma vez
+            assert_eq!(pares.len(), 6);
+            for (idx, (_, sorteado)) in pares.iter().enumerate() {
+                assert_eq!(sorteado.id, pares[(idx + 1) % pares.len()].0.id);
             }
-        };
 
-        results.push(processo.enviar(transport.clone(), conn, &smtp_ctx));
+            for (destino, sorteado) in &pares {
+                assert_ne!(destino.id, sorteado.id);
+                assert!(destino.grupo.is_none() || destino.grupo != sorteado.grupo);
+                assert!(!exclusoes.contains(&(destino.id, sorteado.id)));
+            }
+        }
     }
 
-    return results;
+    #[test]
+    fn sem_corrente_possivel() {
+        let jogadores = vec![
+            jogador(1, Some("silva")),
+            jogador(2, Some("silva")),
+            jogador(3, None),
+        ];
+
+        assert!(sortear(&mut gerador("semente"), jogadores, &[]).is_err());
+    }
 }
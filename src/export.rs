@@ -0,0 +1,287 @@
+//! Exportação de um jogo com todo o seu histórico.
+//!
+//! Os jogadores saem no mesmo formato que os importadores leem, então o
+//! arquivo pode voltar por `jogo from` (os sorteios são ignorados na volta).
+//! Quem tirou quem só aparece se `sorteados` vier preenchido.
+
+use std::{collections::HashMap, io::Write, path::Path};
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::{
+    db::{Envio, ItemOutbox, Jogador, Jogo, Sorteio, TipoEnvio},
+    import::ImportedJogador,
+};
+
+/// Grava `conteudo` em `path`, ou na saída padrão se ele for `-`
+pub fn escrever_saida(path: &Path, conteudo: &str) -> Result<(), String> {
+    let escrito = match path.as_os_str() == "-" {
+        true => std::io::stdout().lock().write_all(conteudo.as_bytes()),
+        false => std::fs::write(path, conteudo),
+    };
+
+    escrito.map_err(|e| format!("Não foi possível escrever {}: {e}", path.display()))
+}
+
+/// Tudo o que a base sabe sobre o jogo
+pub struct Exportacao {
+    pub jogo: Jogo,
+    pub jogadores: Vec<Jogador>,
+    pub exclusoes: Vec<(u64, u64)>,
+    pub sorteios: Vec<(Sorteio, Vec<Envio>, Vec<ItemOutbox>)>,
+}
+
+impl Exportacao {
+    pub fn coletar(conn: &mut Connection, jogo: u64) -> Exportacao {
+        let sorteios = crate::db::sorteio::get_sorteios_by_jogo(conn, jogo)
+            .into_iter()
+            .map(|s| {
+                let envios = crate::db::envios::get_envios_by_sorteio(conn, s.id);
+                let fila = crate::db::outbox::get_itens_by_sorteio(conn, s.id);
+                (s, envios, fila)
+            })
+            .collect();
+
+        let jogadores = crate::db::jogador::get_jogadores_by_jogo(conn, jogo);
+
+        // uma exclusão de quem já saiu do jogo não tem email para ir no
+        // arquivo, e um marcador no lugar dele não passaria pelo `jogo from`
+        let (exclusoes, orfas): (Vec<_>, Vec<_>) =
+            crate::db::exclusoes::get_exclusoes_by_jogo(conn, jogo)
+                .into_iter()
+                .partition(|(j, e)| {
+                    [j, e]
+                        .iter()
+                        .all(|id| jogadores.iter().any(|x| x.id == **id))
+                });
+        for (j, e) in orfas {
+            tracing::warn!(
+                "A exclusão do jogador {j} sobre o {e} aponta para quem não está mais no jogo e ficou de fora"
+            );
+        }
+
+        Exportacao {
+            jogo: crate::db::jogo::get_jogo_by_id(conn, jogo),
+            jogadores,
+            exclusoes,
+            sorteios,
+        }
+    }
+
+    pub fn envios(&self) -> Vec<Envio> {
+        self.sorteios
+            .iter()
+            .flat_map(|(_, envios, _)| envios.iter().cloned())
+            .collect()
+    }
+
+    fn jogador(&self, id: u64) -> Option<&Jogador> {
+        self.jogadores.iter().find(|j| j.id == id)
+    }
+
+    fn email(&self, id: u64) -> String {
+        self.jogador(id)
+            .map(|j| j.email.clone())
+            .unwrap_or(format!("<jogador {id} removido>"))
+    }
+
+    fn importavel(&self, jogador: &Jogador) -> ImportedJogador {
+        ImportedJogador {
+            nome: jogador.nome.clone(),
+            email: jogador.email.clone(),
+            grupo: jogador.grupo.clone(),
+            idioma: jogador.idioma.clone(),
+            lista_desejos: jogador
+                .lista_desejos
+                .as_deref()
+                .map(|l| l.lines().map(str::to_owned).collect())
+                .unwrap_or_default(),
+            exclusoes: self
+                .exclusoes
+                .iter()
+                .filter(|(j, _)| *j == jogador.id)
+                .map(|(_, e)| self.email(*e))
+                .collect(),
+            linha: None,
+        }
+    }
+
+    /// Situação do resultado do sorteio para o destino, para o CSV
+    fn situacao(&self, envios: &[Envio], fila: &[ItemOutbox], destino: u64) -> String {
+        let envio = envios
+            .iter()
+            .filter(|e| e.destino == destino && e.tipo == TipoEnvio::Sorteio)
+            .max_by_key(|e| e.id);
+
+        match envio {
+            Some(e) if e.sucesso => match &e.confirmado_em {
+                Some(c) => format!("entregue, confirmado em {c}"),
+                None => "entregue".to_owned(),
+            },
+            Some(e) => format!("erro: {}", e.erro.as_deref().unwrap_or("desconhecido")),
+            None if fila
+                .iter()
+                .any(|i| i.destino == destino && i.tipo == TipoEnvio::Sorteio) =>
+            {
+                "na fila".to_owned()
+            }
+            None => String::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JogoJson {
+    nome: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_evento: Option<String>,
+    jogadores: Vec<ImportedJogador>,
+    sorteios: Vec<SorteioJson>,
+}
+
+#[derive(Serialize)]
+struct SorteioJson {
+    id: u64,
+    jogadores_qtd: u64,
+    /// A semente nunca sai; só o hash dela, para conferência
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compromisso: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agendado_para: Option<String>,
+    envios: Vec<EnvioJson>,
+    fila: Vec<ItemJson>,
+}
+
+#[derive(Serialize)]
+struct EnvioJson {
+    id: u64,
+    tipo: TipoEnvio,
+    destino: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sorteado: Option<String>,
+    sucesso: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    erro: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confirmado_em: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ItemJson {
+    tipo: TipoEnvio,
+    destino: String,
+    tentativas: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ultimo_erro: Option<String>,
+}
+
+/// O jogo em JSON; `sorteados` leva o id de cada envio ao do seu sorteado
+pub fn json(exportacao: &Exportacao, sorteados: &HashMap<u64, u64>) -> String {
+    let jogo = JogoJson {
+        nome: exportacao.jogo.nome.clone(),
+        data_evento: exportacao.jogo.data_evento.clone(),
+        jogadores: exportacao
+            .jogadores
+            .iter()
+            .map(|j| exportacao.importavel(j))
+            .collect(),
+        sorteios: exportacao
+            .sorteios
+            .iter()
+            .map(|(s, envios, fila)| SorteioJson {
+                id: s.id,
+                jogadores_qtd: s.jogadores_qtd,
+                compromisso: s.compromisso.clone(),
+                agendado_para: s.agendado_para.clone(),
+                envios: envios
+                    .iter()
+                    .map(|e| EnvioJson {
+                        id: e.id,
+                        tipo: e.tipo,
+                        destino: exportacao.email(e.destino),
+                        sorteado: sorteados.get(&e.id).map(|s| exportacao.email(*s)),
+                        sucesso: e.sucesso,
+                        erro: e.erro.clone(),
+                        confirmado_em: e.confirmado_em.clone(),
+                    })
+                    .collect(),
+                fila: fila
+                    .iter()
+                    .map(|i| ItemJson {
+                        tipo: i.tipo,
+                        destino: exportacao.email(i.destino),
+                        tentativas: i.tentativas,
+                        ultimo_erro: i.ultimo_erro.clone(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    serde_json::to_string_pretty(&jogo).unwrap()
+}
+
+/// O jogo em CSV, um jogador por linha, com uma coluna por sorteio para a
+/// situação do resultado (e outra para o sorteado, se houver `sorteados`)
+pub fn csv(exportacao: &Exportacao, sorteados: &HashMap<u64, u64>) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let revelar = !sorteados.is_empty();
+
+    let mut cabecalho = [
+        "Nome",
+        "Email",
+        "Grupo",
+        "Idioma",
+        "Lista de desejos",
+        "Exclusões",
+    ]
+    .map(str::to_owned)
+    .to_vec();
+    for (s, _, _) in &exportacao.sorteios {
+        cabecalho.push(format!("Sorteio {}", s.id));
+        if revelar {
+            cabecalho.push(format!("Sorteio {}: tirou", s.id));
+        }
+    }
+    writer.write_record(&cabecalho).unwrap();
+
+    for j in &exportacao.jogadores {
+        let importavel = exportacao.importavel(j);
+        let mut linha = vec![
+            importavel.nome,
+            importavel.email,
+            importavel.grupo.unwrap_or_default(),
+            importavel.idioma.unwrap_or_default(),
+            juntar_itens(&importavel.lista_desejos),
+            juntar_itens(&importavel.exclusoes),
+        ];
+
+        for (_, envios, fila) in &exportacao.sorteios {
+            linha.push(exportacao.situacao(envios, fila, j.id));
+            if revelar {
+                let sorteado = envios
+                    .iter()
+                    .filter(|e| e.destino == j.id && e.tipo == TipoEnvio::Sorteio)
+                    .find_map(|e| sorteados.get(&e.id))
+                    .and_then(|s| exportacao.jogador(*s))
+                    .map(|s| s.nome.clone());
+                linha.push(sorteado.unwrap_or_default());
+            }
+        }
+
+        writer.write_record(&linha).unwrap();
+    }
+
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+/// Uma lista numa célula só, separada por `;`; um `;` ou `\` dentro de um item
+/// vai escapado com `\`, como o importador de tabelas espera
+pub(crate) fn juntar_itens(itens: &[String]) -> String {
+    itens
+        .iter()
+        .map(|i| i.replace('\\', "\\\\").replace(';', "\\;"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
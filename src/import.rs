@@ -1,58 +1,983 @@
-use serde::Deserialize;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{cli::EscolhaEmail, db::Jogador};
+
+/// Jogo que recebe os jogadores importados
+#[derive(Debug, Clone, Copy)]
+pub enum Destino<'a> {
+    /// Cria um jogo novo com este nome, ou com o que estiver no arquivo
+    Novo(Option<&'a str>),
+    /// Acrescenta a um jogo existente
+    Existente(u64),
+}
+
+/// Como ler o arquivo
+#[derive(Debug, Default, Clone)]
+pub struct Opcoes {
+    /// Coluna de cada campo (`nome`, `email`), pelo título ou pelo número
+    /// (a partir de 1); os campos sem coluna são procurados pelos títulos
+    pub colunas: Vec<(Campo, String)>,
+    /// A primeira linha já é de dados; sem `colunas`, o nome é a primeira
+    /// coluna e o email, a segunda
+    pub sem_cabecalho: bool,
+    /// Qual email usar dos contatos com mais de um
+    pub email: EscolhaEmail,
+    /// Aba das planilhas, pelo nome ou pelo número; sem ela, a primeira
+    pub planilha: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Campo {
+    Nome,
+    Email,
+}
+
+impl std::str::FromStr for Campo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "nome" => Ok(Campo::Nome),
+            "email" => Ok(Campo::Email),
+            outro => Err(format!(
+                "campo `{outro}` desconhecido, use `nome` ou `email`"
+            )),
+        }
+    }
+}
+
+/// O que a importação fez com cada jogador do arquivo
+#[derive(Debug, Default)]
+pub struct Resumo {
+    pub jogo: u64,
+    pub adicionados: Vec<u64>,
+    pub atualizados: Vec<u64>,
+    pub inalterados: Vec<u64>,
+    /// Jogadores do arquivo que já estão na base, um por linha do relatório
+    pub ja_na_base: Vec<String>,
+}
+
+/// Conteúdo de um arquivo de importação. Nos formatos JSON e YAML, é o
+/// próprio formato do arquivo
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Importacao {
+    /// Nome do jogo, usado se não houver `--nome`
+    pub nome: Option<String>,
+    /// Dia da troca de presentes (`AAAA-MM-DD`)
+    pub data_evento: Option<String>,
+    pub jogadores: Vec<ImportedJogador>,
+    /// Histórico que o `jogo export` inclui, ignorado na importação
+    #[serde(default)]
+    pub sorteios: serde::de::IgnoredAny,
+}
+
+/// Um jogador como os importadores o leem; é também o formato dos jogadores
+/// no `jogo export`
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportedJogador {
+    pub nome: String,
+    pub email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grupo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idioma: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lista_desejos: Vec<String>,
+    /// Emails de quem o jogador não pode tirar
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclusoes: Vec<String>,
+    /// Linha do arquivo, nos formatos tabulares
+    #[serde(skip)]
+    pub linha: Option<usize>,
+}
+
+/// Conteúdo de `path`, ou da entrada padrão se ele for `-`
+pub fn ler_entrada(path: &Path) -> Result<Vec<u8>, String> {
+    let lido = match path.as_os_str() == "-" {
+        true => {
+            let mut bytes = vec![];
+            std::io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+        }
+        false => std::fs::read(path),
+    };
+
+    lido.map_err(|e| format!("Não foi possível ler {}: {e}", path.display()))
+}
+
+fn ler_texto(path: &Path) -> Result<String, String> {
+    String::from_utf8(ler_entrada(path)?)
+        .map_err(|e| format!("{} não está em UTF-8: {e}", path.display()))
+}
 
 pub trait Importer {
+    /// Lê o arquivo (ou a entrada padrão, se `path` for `-`), sem tocar na
+    /// base
+    fn ler(path: PathBuf, opcoes: &Opcoes) -> Result<Importacao, String>;
+
+    /// Importa os jogadores do arquivo para o `destino`.
+    ///
+    /// O arquivo inteiro é validado antes de qualquer gravação, e tudo é
+    /// gravado numa única transação, desfeita no fim se for uma `simulacao`.
+    /// Num jogo existente, quem já está nele (pelo email, sem diferenciar
+    /// maiúsculas) é atualizado; os demais são adicionados. Campos opcionais
+    /// ausentes do arquivo mantêm o valor que já estava na base.
+    ///
+    /// Retorna todos os problemas encontrados, se houver algum
     fn from_path(
         path: PathBuf,
+        opcoes: &Opcoes,
         conn: &mut rusqlite::Connection,
-        jogo_nome: &str,
-    ) -> (usize, Vec<usize>);
+        destino: Destino,
+        simulacao: bool,
+    ) -> Result<Resumo, Vec<String>> {
+        let importacao = Self::ler(path, opcoes).map_err(|e| vec![e])?;
+
+        let (nome, existentes) = match destino {
+            Destino::Novo(nome) => (nome.or(importacao.nome.as_deref()), vec![]),
+            Destino::Existente(jogo) => {
+                (None, crate::db::jogador::get_jogadores_by_jogo(conn, jogo))
+            }
+        };
+
+        let mut problemas = validar(&importacao, &existentes);
+        if matches!(destino, Destino::Novo(_)) && nome.is_none() {
+            problemas.insert(0, "o arquivo não diz o nome do jogo; use --nome".to_owned());
+        }
+        if !problemas.is_empty() {
+            return Err(problemas);
+        }
+
+        let todos = crate::db::jogador::get_all_jogadores(conn);
+        let alvo = match destino {
+            Destino::Novo(_) => None,
+            Destino::Existente(jogo) => Some(jogo),
+        };
+        let ja_na_base = ja_na_base(&importacao, &todos, alvo);
+
+        let tx = conn.transaction().unwrap();
+
+        let jogo = match destino {
+            Destino::Novo(_) => {
+                let jogo =
+                    crate::db::jogo::create_jogo_with_nome(&tx, &nome.unwrap().to_owned()) as u64;
+                tracing::info!("Criado jogo com id {jogo}");
+                jogo
+            }
+            Destino::Existente(jogo) => jogo,
+        };
+        let mut resumo = gravar(&tx, jogo, &importacao, existentes);
+        resumo.ja_na_base = ja_na_base;
+
+        match simulacao {
+            true => tx.rollback().unwrap(),
+            false => tx.commit().unwrap(),
+        }
+        Ok(resumo)
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct ImportedJogador {
-    #[serde(rename = "Nome")]
-    nome: String,
-    #[serde(rename = "Email")]
-    email: String,
+impl ImportedJogador {
+    /// Onde o jogador está no arquivo, para os problemas da validação
+    fn onde(&self, indice: usize) -> String {
+        match self.linha {
+            Some(linha) => format!("linha {linha}"),
+            None => format!("jogador {}", indice + 1),
+        }
+    }
 }
 
-pub mod csv {
-    use super::{ImportedJogador, Importer};
-    pub struct CsvImporter {}
+/// Todos os problemas do arquivo de uma vez: data do evento, nomes vazios,
+/// emails inválidos ou repetidos e exclusões de quem não está no jogo
+fn validar(importacao: &Importacao, existentes: &[Jogador]) -> Vec<String> {
+    let mut problemas = vec![];
 
-    impl Importer for CsvImporter {
-        fn from_path(
-            path: std::path::PathBuf,
-            conn: &mut rusqlite::Connection,
-            jogo_nome: &str,
-        ) -> (usize, Vec<usize>) {
-            let mut reader = csv::Reader::from_path(path).unwrap();
-            let jogo = crate::db::jogo::create_jogo_with_nome(conn, &jogo_nome.to_owned());
-            let mut jogadores: Vec<usize> = Vec::new();
+    if let Some(data) = &importacao.data_evento {
+        if let Err(e) = chrono::NaiveDate::parse_from_str(data, "%Y-%m-%d") {
+            problemas.push(format!("data do evento `{data}` inválida: {e}"));
+        }
+    }
+
+    let jogadores = &importacao.jogadores;
+    for (i, j) in jogadores.iter().enumerate() {
+        let onde = j.onde(i);
+
+        if j.nome.trim().is_empty() {
+            problemas.push(format!("{onde}: nome vazio"));
+        }
+
+        if j.email.trim().is_empty() {
+            problemas.push(format!("{onde}: email vazio"));
+            continue;
+        }
+        if j.email.parse::<lettre::Address>().is_err() {
+            problemas.push(format!("{onde}: email `{}` inválido", j.email));
+        }
+
+        let anterior = jogadores[..i]
+            .iter()
+            .enumerate()
+            .find(|(_, a)| a.email.eq_ignore_ascii_case(&j.email));
+        if let Some((k, a)) = anterior {
+            problemas.push(format!(
+                "{onde}: email <{}> repetido ({})",
+                j.email,
+                a.onde(k)
+            ));
+        }
+
+        for e in &j.exclusoes {
+            let conhecido = jogadores.iter().any(|i| i.email.eq_ignore_ascii_case(e))
+                || por_email(existentes, e).is_some();
+            if !conhecido {
+                problemas.push(format!("{onde}: exclui <{e}>, que não está no jogo"));
+            }
+        }
+    }
 
-            tracing::info!("Criado jogo com id {jogo}");
+    problemas
+}
+
+/// Jogadores do arquivo cujo email já está na base: no jogo de destino eles
+/// são atualizados em vez de adicionados; em outros jogos, continuam sendo
+/// jogadores separados
+fn ja_na_base(importacao: &Importacao, todos: &[Jogador], alvo: Option<u64>) -> Vec<String> {
+    let mut relatorio = vec![];
+
+    for (i, j) in importacao.jogadores.iter().enumerate() {
+        let onde = j.onde(i);
+        let iguais = todos
+            .iter()
+            .filter(|e| e.email.eq_ignore_ascii_case(&j.email));
+
+        for e in iguais {
+            relatorio.push(match Some(e.jogo) == alvo {
+                true => format!(
+                    "{onde}: <{}> já está na base como o jogador {} ({}) deste jogo; não será duplicado",
+                    j.email, e.id, e.nome
+                ),
+                false => format!(
+                    "{onde}: <{}> já está na base como o jogador {} ({}) do jogo {}",
+                    j.email, e.id, e.nome, e.jogo
+                ),
+            });
+        }
+    }
+
+    relatorio
+}
+
+/// Grava a importação já validada no `jogo`
+fn gravar(
+    conn: &rusqlite::Connection,
+    jogo: u64,
+    importacao: &Importacao,
+    mut existentes: Vec<Jogador>,
+) -> Resumo {
+    if let Some(data) = &importacao.data_evento {
+        crate::db::jogo::update_data_evento(conn, jogo, Some(data));
+    }
+
+    let mut resumo = Resumo {
+        jogo,
+        ..Default::default()
+    };
+
+    for record in &importacao.jogadores {
+        let lista_desejos =
+            (!record.lista_desejos.is_empty()).then(|| record.lista_desejos.join("\n"));
+
+        match existentes
+            .iter_mut()
+            .find(|j| j.email.eq_ignore_ascii_case(&record.email))
+        {
+            Some(j) => {
+                let grupo = record.grupo.clone().or(j.grupo.clone());
+                let idioma = record.idioma.clone().or(j.idioma.clone());
+                let lista_desejos = lista_desejos.or(j.lista_desejos.clone());
+
+                if j.nome == record.nome
+                    && j.grupo == grupo
+                    && j.idioma == idioma
+                    && j.lista_desejos == lista_desejos
+                {
+                    resumo.inalterados.push(j.id);
+                    continue;
+                }
 
-            for r in reader.deserialize() {
-                let record: ImportedJogador = r.unwrap();
+                if j.nome != record.nome {
+                    crate::db::jogador::update_jogador_by_collumn(
+                        conn,
+                        &"nome".to_owned(),
+                        record.nome.clone(),
+                        j.id,
+                    );
+                    tracing::info!(
+                        "Atualizado jogador com id {}: nome `{}` para `{}`",
+                        j.id,
+                        j.nome,
+                        record.nome
+                    );
+                }
+                crate::db::jogador::update_preferencias(
+                    conn,
+                    j.id,
+                    grupo.as_ref(),
+                    idioma.as_ref(),
+                    lista_desejos.as_ref(),
+                );
 
+                j.nome = record.nome.clone();
+                (j.grupo, j.idioma, j.lista_desejos) = (grupo, idioma, lista_desejos);
+                resumo.atualizados.push(j.id);
+            }
+            None => {
                 let id = crate::db::jogador::create_jogador(
                     conn,
-                    jogo.try_into().unwrap(),
+                    jogo,
                     record.nome.clone(),
                     record.email.clone(),
+                ) as u64;
+                crate::db::jogador::update_preferencias(
+                    conn,
+                    id,
+                    record.grupo.as_ref(),
+                    record.idioma.as_ref(),
+                    lista_desejos.as_ref(),
                 );
 
                 tracing::info!(
                     "Criado jogador com id {id}, nome {} e email <{}>",
-                    record.nome.clone(),
-                    record.email.clone()
+                    record.nome,
+                    record.email
                 );
 
-                jogadores.push(id);
+                existentes.push(crate::db::jogador::get_jogador_by_id(conn, id));
+                resumo.adicionados.push(id);
+            }
+        }
+    }
+
+    // as exclusões só são gravadas depois, quando todos já têm id
+    let atuais = crate::db::exclusoes::get_exclusoes_by_jogo(conn, jogo);
+    for record in importacao
+        .jogadores
+        .iter()
+        .filter(|r| !r.exclusoes.is_empty())
+    {
+        let id = por_email(&existentes, &record.email).unwrap().id;
+        let mut excluidos = record
+            .exclusoes
+            .iter()
+            .map(|e| por_email(&existentes, e).unwrap().id)
+            .filter(|e| *e != id)
+            .collect::<Vec<_>>();
+        excluidos.sort();
+        excluidos.dedup();
+
+        let mut antes = atuais
+            .iter()
+            .filter(|(j, _)| *j == id)
+            .map(|(_, e)| *e)
+            .collect::<Vec<_>>();
+        antes.sort();
+
+        if antes != excluidos {
+            crate::db::exclusoes::replace_exclusoes(conn, id, &excluidos);
+            tracing::info!("Atualizadas as exclusões do jogador com id {id}");
+
+            if let Some(i) = resumo.inalterados.iter().position(|j| *j == id) {
+                resumo.inalterados.remove(i);
+                resumo.atualizados.push(id);
+            }
+        }
+    }
+
+    resumo
+}
+
+fn por_email<'a>(jogadores: &'a [Jogador], email: &str) -> Option<&'a Jogador> {
+    jogadores
+        .iter()
+        .find(|j| j.email.eq_ignore_ascii_case(email))
+}
+
+/// Leitura comum dos formatos tabulares (CSV e planilhas): acha as colunas de
+/// nome e email e monta os jogadores, linha a linha
+mod tabela {
+    use super::{Campo, Importacao, ImportedJogador, Opcoes};
+
+    /// Títulos reconhecidos para cada campo, já normalizados
+    const NOMES: &[&str] = &[
+        "nome",
+        "nome completo",
+        "seu nome",
+        "participante",
+        "jogador",
+        "name",
+        "full name",
+    ];
+    const EMAILS: &[&str] = &[
+        "email",
+        "e mail",
+        "seu email",
+        "endereco de email",
+        "endereco de e mail",
+        "correio eletronico",
+        "email address",
+    ];
+    /// Colunas opcionais, só reconhecidas pelo título. As de lista separam os
+    /// itens por `;`, como o `jogo export` os escreve
+    const GRUPOS: &[&str] = &["grupo", "familia", "group"];
+    const IDIOMAS: &[&str] = &["idioma", "lingua", "language"];
+    const LISTAS: &[&str] = &["lista de desejos", "desejos", "wishlist"];
+    const EXCLUSOES: &[&str] = &["exclusoes", "nao pode tirar"];
+
+    pub fn ler(linhas: Vec<Vec<String>>, opcoes: &Opcoes) -> Result<Importacao, String> {
+        let mut linhas = linhas.into_iter().enumerate();
+
+        let cabecalho = match opcoes.sem_cabecalho {
+            true => None,
+            false => match linhas.next() {
+                Some((_, r)) => Some(r),
+                None => return Ok(Importacao::default()),
+            },
+        };
+
+        let nome = coluna(Campo::Nome, cabecalho.as_deref(), opcoes)?;
+        let email = coluna(Campo::Email, cabecalho.as_deref(), opcoes)?;
+        let opcional = |aliases: &[&str]| {
+            cabecalho
+                .as_deref()?
+                .iter()
+                .position(|t| aliases.contains(&normalizar(t).as_str()))
+        };
+        let (grupo, idioma) = (opcional(GRUPOS), opcional(IDIOMAS));
+        let (lista, exclusoes) = (opcional(LISTAS), opcional(EXCLUSOES));
+
+        let mut jogadores = vec![];
+        for (i, r) in linhas {
+            let linha = i + 1;
+            if r.iter().all(|c| c.trim().is_empty()) {
+                continue;
+            }
+
+            let campo = |c: usize| r.get(c).map(|v| v.trim().to_owned()).unwrap_or_default();
+            let texto = |c: Option<usize>| c.map(campo).filter(|v| !v.is_empty());
+            let itens = |c: Option<usize>| texto(c).map(|v| separar_itens(&v)).unwrap_or_default();
+
+            jogadores.push(ImportedJogador {
+                nome: campo(nome),
+                email: campo(email),
+                grupo: texto(grupo),
+                idioma: texto(idioma),
+                lista_desejos: itens(lista),
+                exclusoes: itens(exclusoes),
+                linha: Some(linha),
+            });
+        }
+
+        Ok(Importacao {
+            jogadores,
+            ..Default::default()
+        })
+    }
+
+    /// Índice da coluna do campo: a de `--map`, ou a que tiver um título
+    /// conhecido
+    fn coluna(
+        campo: Campo,
+        cabecalho: Option<&[String]>,
+        opcoes: &Opcoes,
+    ) -> Result<usize, String> {
+        let nome_campo = match campo {
+            Campo::Nome => "nome",
+            Campo::Email => "email",
+        };
+
+        if let Some((_, col)) = opcoes.colunas.iter().find(|(c, _)| *c == campo) {
+            if let Ok(n) = col.trim().parse::<usize>() {
+                return n
+                    .checked_sub(1)
+                    .ok_or(format!("as colunas são numeradas a partir de 1, não {n}"));
             }
 
-            return (jogo, jogadores);
+            return cabecalho
+                .and_then(|cab| cab.iter().position(|t| normalizar(t) == normalizar(col)))
+                .ok_or(format!("coluna `{col}` do {nome_campo} não encontrada"));
+        }
+
+        let Some(cabecalho) = cabecalho else {
+            return Ok(match campo {
+                Campo::Nome => 0,
+                Campo::Email => 1,
+            });
+        };
+
+        let aliases = match campo {
+            Campo::Nome => NOMES,
+            Campo::Email => EMAILS,
+        };
+        cabecalho
+            .iter()
+            .position(|t| aliases.contains(&normalizar(t).as_str()))
+            .ok_or(format!(
+                "nenhuma coluna de {nome_campo} entre {:?}; indique-a com --map {nome_campo}=<coluna>",
+                cabecalho.iter().collect::<Vec<_>>()
+            ))
+    }
+
+    /// Minúsculas, sem acentos e só com letras, números e espaços simples
+    fn normalizar(titulo: &str) -> String {
+        titulo
+            .to_lowercase()
+            .chars()
+            .map(|c| match c {
+                'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+                'é' | 'è' | 'ê' | 'ë' => 'e',
+                'í' | 'ì' | 'î' | 'ï' => 'i',
+                'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+                'ú' | 'ù' | 'û' | 'ü' => 'u',
+                'ç' => 'c',
+                c if c.is_alphanumeric() => c,
+                _ => ' ',
+            })
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Itens de uma célula separados por `;`, onde `\;` e `\\` são um `;` e
+    /// uma `\` do próprio item (como o `jogo export` os escreve)
+    pub(super) fn separar_itens(valor: &str) -> Vec<String> {
+        let mut itens = vec![];
+        let mut atual = String::new();
+        let mut chars = valor.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(c @ (';' | '\\')) => atual.push(c),
+                    Some(c) => {
+                        atual.push('\\');
+                        atual.push(c);
+                    }
+                    None => atual.push('\\'),
+                },
+                ';' => itens.push(std::mem::take(&mut atual)),
+                c => atual.push(c),
+            }
         }
+        itens.push(atual);
+
+        itens
+            .into_iter()
+            .map(|i| i.trim().to_owned())
+            .filter(|i| !i.is_empty())
+            .collect()
+    }
+}
+
+pub mod csv {
+    use std::collections::HashMap;
+
+    use super::{Importacao, Importer, Opcoes};
+
+    /// Lê CSVs de várias origens: detecta o separador (`,`, `;`, tab ou `|`),
+    /// aceita UTF-8 ou Windows-1252 (o Latin-1 do Excel) e reconhece os
+    /// títulos mais comuns das colunas, como os do Google Forms
+    pub struct CsvImporter {}
+
+    impl Importer for CsvImporter {
+        fn ler(path: std::path::PathBuf, opcoes: &Opcoes) -> Result<Importacao, String> {
+            let bytes = super::ler_entrada(&path)?;
+            let texto = decodificar(&bytes);
+
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(separador(&texto))
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(texto.as_bytes());
+
+            let linhas = reader
+                .records()
+                .enumerate()
+                .map(|(i, r)| {
+                    r.map(|r| r.iter().map(str::to_owned).collect())
+                        .map_err(|e| format!("linha {}: {e}", i + 1))
+                })
+                .collect::<Result<Vec<Vec<String>>, String>>()?;
+
+            super::tabela::ler(linhas, opcoes)
+        }
+    }
+
+    /// UTF-8 (sem o BOM) se for válido; senão, Windows-1252
+    fn decodificar(bytes: &[u8]) -> String {
+        let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+
+        match std::str::from_utf8(bytes) {
+            Ok(texto) => texto.to_owned(),
+            Err(_) => {
+                tracing::debug!("Arquivo não é UTF-8, lendo como Windows-1252");
+                encoding_rs::WINDOWS_1252.decode(bytes).0.into_owned()
+            }
+        }
+    }
+
+    /// O candidato que mais aparece na primeira linha, fora de aspas; nos
+    /// empates, o que vem antes na lista
+    fn separador(texto: &str) -> u8 {
+        let primeira = texto.lines().next().unwrap_or_default();
+        let mut contagem: HashMap<char, usize> = HashMap::new();
+        let mut aspas = false;
+
+        for c in primeira.chars() {
+            match c {
+                '"' => aspas = !aspas,
+                ',' | ';' | '\t' | '|' if !aspas => *contagem.entry(c).or_default() += 1,
+                _ => (),
+            }
+        }
+
+        [',', ';', '\t', '|']
+            .into_iter()
+            .rev()
+            .max_by_key(|c| contagem.get(c).copied().unwrap_or_default())
+            .filter(|c| contagem.contains_key(c))
+            .unwrap_or(',') as u8
+    }
+}
+
+/// Planilhas do Excel e do LibreOffice, com as mesmas colunas que o CSV
+pub mod planilha {
+    use std::io::Cursor;
+
+    use calamine::{Ods, Reader, Xlsx};
+
+    use super::{Importacao, Importer, Opcoes};
+
+    pub struct XlsxImporter {}
+
+    pub struct OdsImporter {}
+
+    impl Importer for XlsxImporter {
+        fn ler(path: std::path::PathBuf, opcoes: &Opcoes) -> Result<Importacao, String> {
+            let pasta = Xlsx::new(Cursor::new(super::ler_entrada(&path)?))
+                .map_err(|e| format!("Não foi possível abrir {}: {e}", path.display()))?;
+            ler(pasta, opcoes)
+        }
+    }
+
+    impl Importer for OdsImporter {
+        fn ler(path: std::path::PathBuf, opcoes: &Opcoes) -> Result<Importacao, String> {
+            let pasta = Ods::new(Cursor::new(super::ler_entrada(&path)?))
+                .map_err(|e| format!("Não foi possível abrir {}: {e}", path.display()))?;
+            ler(pasta, opcoes)
+        }
+    }
+
+    /// Lê a aba escolhida em `opcoes.planilha` (pelo nome ou pelo número, a
+    /// partir de 1), ou a primeira
+    fn ler<RS, R>(mut pasta: R, opcoes: &Opcoes) -> Result<Importacao, String>
+    where
+        RS: std::io::Read + std::io::Seek,
+        R: Reader<RS>,
+        R::Error: std::fmt::Display,
+    {
+        let abas = pasta.sheet_names();
+        let aba = match &opcoes.planilha {
+            None => abas.first(),
+            Some(p) => match p.trim().parse::<usize>() {
+                Ok(n) => n.checked_sub(1).and_then(|n| abas.get(n)),
+                Err(_) => abas.iter().find(|a| a.eq_ignore_ascii_case(p.trim())),
+            },
+        }
+        .ok_or(format!(
+            "aba `{}` não encontrada entre {abas:?}",
+            opcoes.planilha.as_deref().unwrap_or("1")
+        ))?
+        .clone();
+
+        let linhas = pasta
+            .worksheet_range(&aba)
+            .map_err(|e| format!("Não foi possível ler a aba `{aba}`: {e}"))?
+            .rows()
+            .map(|r| r.iter().map(|c| c.to_string()).collect())
+            .collect();
+
+        super::tabela::ler(linhas, opcoes)
+    }
+}
+
+pub mod json {
+    use super::{Importacao, Importer, Opcoes};
+
+    /// Um jogo inteiro declarado em JSON, no formato de `Importacao`
+    pub struct JsonImporter {}
+
+    impl Importer for JsonImporter {
+        fn ler(path: std::path::PathBuf, _: &Opcoes) -> Result<Importacao, String> {
+            let texto = super::ler_texto(&path)?;
+
+            serde_json::from_str(&texto).map_err(|e| format!("JSON inválido: {e}"))
+        }
+    }
+}
+
+pub mod yaml {
+    use super::{Importacao, Importer, Opcoes};
+
+    /// O mesmo que o `JsonImporter`, em YAML
+    pub struct YamlImporter {}
+
+    impl Importer for YamlImporter {
+        fn ler(path: std::path::PathBuf, _: &Opcoes) -> Result<Importacao, String> {
+            let texto = super::ler_texto(&path)?;
+
+            serde_yaml::from_str(&texto).map_err(|e| format!("YAML inválido: {e}"))
+        }
+    }
+}
+
+pub mod vcf {
+    use super::{Importacao, ImportedJogador, Importer, Opcoes};
+    use crate::cli::EscolhaEmail;
+
+    /// Contatos vCard. Usa o `FN` como nome (ou o `N`, se faltar) e pula, com
+    /// um aviso, os contatos sem email
+    pub struct VcfImporter {}
+
+    #[derive(Debug, Default)]
+    struct Contato {
+        nome: Option<String>,
+        /// Partes do `N`: sobrenome, nome, nomes do meio...
+        partes_nome: Vec<String>,
+        /// Emails e se cada um é o preferido
+        emails: Vec<(String, bool)>,
+    }
+
+    impl Importer for VcfImporter {
+        fn ler(path: std::path::PathBuf, opcoes: &Opcoes) -> Result<Importacao, String> {
+            let texto = super::ler_texto(&path)?;
+
+            let mut jogadores = vec![];
+            for contato in contatos(&texto)? {
+                let nome = contato.nome.clone().or_else(|| {
+                    let [sobrenome, nome, ..] = contato.partes_nome.as_slice() else {
+                        return None;
+                    };
+                    let completo = format!("{nome} {sobrenome}").trim().to_owned();
+                    (!completo.is_empty()).then_some(completo)
+                });
+
+                let Some(nome) = nome else {
+                    tracing::warn!("Contato sem nome pulado: {:?}", contato.emails);
+                    continue;
+                };
+
+                let Some(email) = escolher_email(&nome, &contato.emails, opcoes.email) else {
+                    tracing::warn!("Contato `{nome}` pulado: sem email");
+                    continue;
+                };
+
+                jogadores.push(ImportedJogador {
+                    nome,
+                    email,
+                    ..Default::default()
+                });
+            }
+
+            Ok(Importacao {
+                jogadores,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn escolher_email(
+        nome: &str,
+        emails: &[(String, bool)],
+        escolha: EscolhaEmail,
+    ) -> Option<String> {
+        if emails.len() <= 1 {
+            return emails.first().map(|(e, _)| e.clone());
+        }
+
+        let i = match escolha {
+            EscolhaEmail::Preferido => emails.iter().position(|(_, pref)| *pref).unwrap_or(0),
+            EscolhaEmail::Primeiro => 0,
+            EscolhaEmail::Perguntar => crate::prompt::escolher(
+                &format!("`{nome}` tem {} emails:", emails.len()),
+                &emails
+                    .iter()
+                    .map(|(e, pref)| match pref {
+                        true => format!("{e} (preferido)"),
+                        false => e.clone(),
+                    })
+                    .collect::<Vec<_>>(),
+            )?,
+        };
+
+        Some(emails[i].0.clone())
+    }
+
+    /// Separa os contatos do arquivo, desdobrando as linhas continuadas
+    fn contatos(texto: &str) -> Result<Vec<Contato>, String> {
+        let mut linhas: Vec<String> = vec![];
+        for linha in texto.lines() {
+            match linhas.last_mut() {
+                // continuação de linha (RFC 6350) ou soft break de quoted-printable (vCard 2.1)
+                Some(anterior) if linha.starts_with([' ', '\t']) => anterior.push_str(&linha[1..]),
+                Some(anterior) if anterior.ends_with('=') && eh_quoted_printable(anterior) => {
+                    anterior.pop();
+                    anterior.push_str(linha);
+                }
+                _ => linhas.push(linha.to_owned()),
+            }
+        }
+
+        let mut contatos = vec![];
+        let mut atual: Option<Contato> = None;
+
+        for (i, linha) in linhas.iter().enumerate() {
+            let Some((chave, valor)) = linha.split_once(':') else {
+                continue;
+            };
+            let mut parametros = chave.split(';');
+            // propriedades agrupadas, como `item1.EMAIL`
+            let propriedade = parametros
+                .next()
+                .unwrap_or_default()
+                .rsplit('.')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_uppercase();
+            let parametros = parametros
+                .map(|p| p.to_ascii_uppercase())
+                .collect::<Vec<_>>();
+
+            let valor = match parametros.iter().any(|p| p == "ENCODING=QUOTED-PRINTABLE") {
+                true => decodificar_quoted_printable(valor),
+                false => valor.to_owned(),
+            };
+
+            match (propriedade.as_str(), &mut atual) {
+                ("BEGIN", None) if valor.eq_ignore_ascii_case("VCARD") => {
+                    atual = Some(Contato::default())
+                }
+                ("END", Some(_)) if valor.eq_ignore_ascii_case("VCARD") => {
+                    contatos.push(atual.take().unwrap())
+                }
+                ("BEGIN" | "END", _) => {
+                    return Err(format!("vCard mal formado perto da linha {}", i + 1))
+                }
+                ("FN", Some(c)) => c.nome = Some(desescapar(&valor)).filter(|n| !n.is_empty()),
+                ("N", Some(c)) => c.partes_nome = valor.split(';').map(desescapar).collect(),
+                ("EMAIL", Some(c)) => {
+                    let email = desescapar(&valor);
+                    if !email.is_empty() {
+                        c.emails.push((email, preferido(&parametros)));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if atual.is_some() {
+            return Err("vCard sem END:VCARD no fim do arquivo".to_owned());
+        }
+
+        Ok(contatos)
+    }
+
+    /// `PREF` (2.1), `TYPE=PREF` (3) ou `PREF=1` (4)
+    fn preferido(parametros: &[String]) -> bool {
+        parametros.iter().any(|p| {
+            p == "PREF"
+                || p == "PREF=1"
+                || p.strip_prefix("TYPE=")
+                    .is_some_and(|t| t.split(',').any(|t| t.trim_matches('"') == "PREF"))
+        })
+    }
+
+    fn eh_quoted_printable(linha: &str) -> bool {
+        linha
+            .split_once(':')
+            .is_some_and(|(chave, _)| chave.to_ascii_uppercase().contains("QUOTED-PRINTABLE"))
+    }
+
+    fn decodificar_quoted_printable(valor: &str) -> String {
+        let bytes = valor.as_bytes();
+        let mut saida = vec![];
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            match (bytes[i], hex) {
+                (b'=', Some(b)) => {
+                    saida.push(b);
+                    i += 3;
+                }
+                (b, _) => {
+                    saida.push(b);
+                    i += 1;
+                }
+            }
+        }
+
+        String::from_utf8_lossy(&saida).into_owned()
+    }
+
+    fn desescapar(valor: &str) -> String {
+        let mut saida = String::new();
+        let mut chars = valor.trim().chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n' | 'N') => saida.push('\n'),
+                    Some(c) => saida.push(c),
+                    None => (),
+                },
+                c => saida.push(c),
+            }
+        }
+
+        saida
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tabela::separar_itens;
+    use crate::export::juntar_itens;
+
+    #[test]
+    fn itens_com_separador_voltam_iguais() {
+        let itens = vec![
+            "livro; de preferência usado".to_owned(),
+            "café".to_owned(),
+            "C:\\fotos\\".to_owned(),
+        ];
+
+        assert_eq!(separar_itens(&juntar_itens(&itens)), itens);
+    }
+
+    #[test]
+    fn itens_sem_escape() {
+        assert_eq!(
+            separar_itens(" livro ;café;; a\\b "),
+            vec!["livro", "café", "a\\b"]
+        );
     }
 }
@@ -1,9 +1,17 @@
 pub mod actions;
+pub mod arquivo;
+pub mod bounces;
 pub mod cli;
 pub mod config;
+pub mod cripto;
 pub mod db;
+pub mod dkim;
 pub mod envio;
+mod export;
 pub mod import;
+pub mod openpgp;
+pub mod organizador;
+pub mod prompt;
 
 use crate::cli::{Arguments, Commands};
 use clap::Parser;
@@ -37,6 +45,7 @@ fn main() {
     tracing_subscriber::fmt()
         .event_format(tracing_subscriber::fmt::format().compact())
         .with_env_filter(crate_filter)
+        .with_writer(std::io::stderr)
         .init();
 
     tracing::debug!("{:?}", args);
@@ -53,10 +62,27 @@ fn exec_args(args: Arguments, conn: &mut Connection, ctx: &Config) {
             JogoAction::Ls => actions::jogo::jogo_ls(conn),
             JogoAction::New { name: nome } => actions::jogo::jogo_new(conn, nome),
             JogoAction::Rm { id } => actions::jogo::jogo_rm(conn, id),
-            JogoAction::From { format, path, nome } => {
-                actions::jogo::jogo_from(conn, format, path, nome)
-            }
-            JogoAction::Inspect { id } => actions::jogo::jogo_inspect(conn, id),
+            JogoAction::From {
+                format,
+                path,
+                nome,
+                into,
+                colunas,
+                no_header,
+                email,
+                sheet,
+                dry_run,
+            } => actions::jogo::jogo_from(
+                conn, format, path, nome, into, colunas, no_header, email, sheet, dry_run,
+            ),
+            JogoAction::Inspect { id } => actions::jogo::jogo_inspect(conn, ctx, id),
+            JogoAction::Set { id, param } => actions::jogo::jogo_set(conn, id, param),
+            JogoAction::Export {
+                id,
+                format,
+                path,
+                reveal,
+            } => actions::jogo::jogo_export(conn, ctx, id, format, path, reveal),
         },
 
         Commands::Jogadores { action } => match action {
@@ -75,22 +101,66 @@ fn exec_args(args: Arguments, conn: &mut Connection, ctx: &Config) {
         },
 
         Commands::Sorteio { action } => match action {
-            SorteioAction::New { jogo } => actions::sorteio::sorteio_new(conn, jogo),
-            SorteioAction::Run { sorteio } => actions::sorteio::sorteio_run(conn, sorteio, ctx),
+            SorteioAction::New { jogo, selar } => {
+                actions::sorteio::sorteio_new(conn, ctx, jogo, selar)
+            }
+            SorteioAction::Run { sorteio, at } => {
+                actions::sorteio::sorteio_run(conn, sorteio, ctx, at)
+            }
             SorteioAction::Ls { jogo } => match jogo {
-                Some(j) => actions::sorteio::sorteios_ls_by_jogo(conn, j),
-                None => actions::sorteio::sorteio_ls(conn),
+                Some(j) => actions::sorteio::sorteios_ls_by_jogo(conn, ctx, j),
+                None => actions::sorteio::sorteio_ls(conn, ctx),
             },
-            SorteioAction::Inspect { sorteio } => actions::sorteio::sorteio_inspect(conn, sorteio),
+            SorteioAction::Inspect { sorteio } => {
+                actions::sorteio::sorteio_inspect(conn, ctx, sorteio)
+            }
+            SorteioAction::Seal { sorteio } => actions::sorteio::sorteio_seal(conn, ctx, sorteio),
+            SorteioAction::DiscardSeed { sorteio } => {
+                actions::sorteio::sorteio_discard_seed(conn, sorteio)
+            }
+            SorteioAction::Verify { sorteio, seed } => {
+                actions::sorteio::sorteio_verify(conn, sorteio, seed)
+            }
+            SorteioAction::Reveal { sorteio, force } => {
+                actions::sorteio::sorteio_reveal(conn, ctx, sorteio, force)
+            }
         },
 
         Commands::Envio { action } => match action {
-            cli::EnvioAction::Inspect { envio } => actions::envio::envio_inspect(conn, envio),
+            cli::EnvioAction::Inspect { envio, reveal } => {
+                actions::envio::envio_inspect(conn, ctx, envio, reveal)
+            }
             cli::EnvioAction::Redo { envio } => actions::envio::envio_redo(conn, ctx, envio),
-            cli::EnvioAction::Ls { sorteio } => match sorteio {
-                Some(s) => actions::envio::envio_ls_with_sorteio(conn, s),
-                None => actions::envio::envio_ls_all(conn),
+            cli::EnvioAction::Bounces { maildir, mbox } => {
+                actions::envio::envio_bounces(conn, maildir, mbox)
+            }
+            cli::EnvioAction::Ls { sorteio, reveal } => match sorteio {
+                Some(s) => actions::envio::envio_ls_with_sorteio(conn, ctx, s, reveal),
+                None => actions::envio::envio_ls_all(conn, ctx, reveal),
             },
         },
+
+        Commands::Smtp { action } => match action {
+            cli::SmtpAction::Test { to } => actions::smtp::smtp_test(ctx, to),
+        },
+
+        Commands::Archive { action } => match action {
+            cli::ArchiveAction::Export { jogos, path } => {
+                actions::archive::archive_export(conn, jogos, path)
+            }
+            cli::ArchiveAction::Import { path } => {
+                actions::archive::archive_import(conn, ctx, path)
+            }
+        },
+
+        Commands::Db { action } => match action {
+            cli::DbAction::Backup { path } => actions::db::db_backup(conn, path),
+            cli::DbAction::Restore { path, force } => {
+                actions::db::db_restore(conn, ctx, path, force)
+            }
+        },
+
+        Commands::Worker { daemon } => actions::worker::worker_run(conn, ctx, daemon),
+        Commands::Serve { addr } => actions::serve::serve_run(conn, ctx, addr),
     }
 }
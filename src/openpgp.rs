@@ -0,0 +1,60 @@
+//! Mensagens PGP/MIME (RFC 3156) para jogadores que cadastraram uma chave
+//! pública OpenPGP.
+
+use lettre::message::{header::ContentType, MultiPart, SinglePart};
+use pgp::{
+    composed::{Deserializable, MessageBuilder, SignedPublicKey},
+    crypto::sym::SymmetricKeyAlgorithm,
+    types::KeyDetails,
+};
+use rand::thread_rng;
+
+/// Lê uma chave pública em formato ASCII armor, conferindo as assinaturas
+pub fn ler_chave(armored: &str) -> Result<SignedPublicKey, String> {
+    let (chave, _) = SignedPublicKey::from_string(armored).map_err(|e| e.to_string())?;
+    chave.verify_bindings().map_err(|e| e.to_string())?;
+
+    Ok(chave)
+}
+
+/// Cifra `corpo` para a chave e o embrulha num `multipart/encrypted`.
+///
+/// Usa a primeira subchave capaz de cifrar, ou a chave primária se não houver
+pub fn cifrar_corpo(armored: &str, corpo: String) -> Result<MultiPart, String> {
+    let chave = ler_chave(armored)?;
+    let interno = SinglePart::plain(corpo).formatted();
+
+    let mut builder = MessageBuilder::from_bytes("", interno)
+        .seipd_v1(thread_rng(), SymmetricKeyAlgorithm::AES256);
+
+    let resultado = match chave
+        .public_subkeys
+        .iter()
+        .find(|s| s.algorithm().can_encrypt())
+    {
+        Some(subchave) => builder.encrypt_to_key(thread_rng(), subchave),
+        None if chave.primary_key.algorithm().can_encrypt() => {
+            builder.encrypt_to_key(thread_rng(), &chave.primary_key)
+        }
+        None => return Err("a chave não tem nenhuma subchave de cifragem".to_owned()),
+    };
+    resultado.map_err(|e| e.to_string())?;
+
+    let cifrado = builder
+        .to_armored_string(thread_rng(), Default::default())
+        .map_err(|e| e.to_string())?;
+
+    Ok(MultiPart::encrypted("application/pgp-encrypted".to_owned())
+        .singlepart(
+            SinglePart::builder()
+                .header(ContentType::parse("application/pgp-encrypted").unwrap())
+                .body("Version: 1\r\n".to_owned()),
+        )
+        .singlepart(
+            SinglePart::builder()
+                .header(
+                    ContentType::parse("application/octet-stream; name=\"encrypted.asc\"").unwrap(),
+                )
+                .body(cifrado),
+        ))
+}
@@ -0,0 +1,148 @@
+//! Avisos ao organizador (`ORGANIZER_EMAIL`).
+//!
+//! Depois de cada rodada de envios ele recebe um resumo por sorteio, com as
+//! contagens e as falhas, e, com `ORGANIZER_RECEIPTS`, um recibo separado por
+//! mensagem entregue (em vez de uma cópia oculta, que mostraria o sorteado).
+//! Nenhum dos avisos diz quem tirou quem.
+
+use std::collections::BTreeSet;
+
+use lettre::Message;
+use rusqlite::Connection;
+
+use crate::{
+    config::Config,
+    db::{Envio, TipoEnvio},
+    envio::enviar_avulsas,
+};
+
+/// Avisa o organizador sobre os envios recém-registrados, se configurado
+pub fn avisar(conn: &mut Connection, ctx: &Config, envios: &[usize]) {
+    if ctx.organizador_email.is_none() || envios.is_empty() {
+        return;
+    }
+
+    let envios = envios
+        .iter()
+        .map(|id| crate::db::envios::get_envio_by_id(conn, *id as u64))
+        .collect::<Vec<_>>();
+
+    let mut avisos = vec![];
+
+    if ctx.organizador_recibos {
+        for e in envios.iter().filter(|e| e.sucesso) {
+            let destino = crate::db::jogador::get_jogador_by_id(conn, e.destino);
+            avisos.extend(mensagem(
+                ctx,
+                format!("{}: {} entregue", ctx.subject, nome_tipo(e.tipo)),
+                format!(
+                    "A mensagem ({}) do sorteio {} foi entregue a {} <{}>.",
+                    nome_tipo(e.tipo),
+                    e.sorteio,
+                    destino.nome,
+                    destino.email
+                ),
+            ));
+        }
+    }
+
+    let sorteios = envios.iter().map(|e| e.sorteio).collect::<BTreeSet<_>>();
+    for sorteio in sorteios {
+        let corpo = resumo(conn, sorteio);
+        avisos.extend(mensagem(
+            ctx,
+            format!("{}: resumo do sorteio {}", ctx.subject, sorteio),
+            corpo,
+        ));
+    }
+
+    for resultado in enviar_avulsas(ctx, avisos) {
+        if let Err(e) = resultado {
+            tracing::warn!("Não foi possível avisar o organizador: {e}");
+        }
+    }
+}
+
+/// Situação atual de todos os envios do sorteio, sem os sorteados
+fn resumo(conn: &mut Connection, sorteio: u64) -> String {
+    let jogo = crate::db::sorteio::get_sorteio_by_id(conn, &sorteio).jogo;
+    let jogo = crate::db::jogo::get_jogo_by_id(conn, jogo);
+    let envios = crate::db::envios::get_envios_by_sorteio(conn, sorteio);
+    let fila = crate::db::outbox::get_itens_by_sorteio(conn, sorteio);
+
+    let mut corpo = format!("Sorteio {} do jogo `{}`\n\n", sorteio, jogo.nome);
+
+    for tipo in [
+        TipoEnvio::Sorteio,
+        TipoEnvio::Lembrete,
+        TipoEnvio::Revelacao,
+    ] {
+        let do_tipo = envios.iter().filter(|e| e.tipo == tipo).collect::<Vec<_>>();
+        let na_fila = fila.iter().filter(|i| i.tipo == tipo).count();
+        if do_tipo.is_empty() && na_fila == 0 {
+            continue;
+        }
+
+        let entregues = do_tipo.iter().filter(|e| e.sucesso).count();
+        corpo.push_str(&format!(
+            "{}: {} entregues, {} com erro, {} na fila\n",
+            nome_tipo(tipo),
+            entregues,
+            do_tipo.len() - entregues,
+            na_fila
+        ));
+    }
+
+    let falhas = envios
+        .iter()
+        .filter(|e| !e.sucesso)
+        .collect::<Vec<&Envio>>();
+    if !falhas.is_empty() {
+        corpo.push_str("\nFalhas:\n");
+        for e in falhas {
+            let destino = crate::db::jogador::get_jogador_by_id(conn, e.destino);
+            corpo.push_str(&format!(
+                "- {} <{}> ({}): {}\n",
+                destino.nome,
+                destino.email,
+                nome_tipo(e.tipo),
+                e.erro.as_deref().unwrap_or("erro desconhecido")
+            ));
+        }
+    }
+
+    corpo
+}
+
+fn nome_tipo(tipo: TipoEnvio) -> &'static str {
+    match tipo {
+        TipoEnvio::Sorteio => "resultado",
+        TipoEnvio::Lembrete => "lembrete",
+        TipoEnvio::Revelacao => "revelação",
+    }
+}
+
+/// Monta um aviso; uma falha no envio só é registrada no log, sem afetar os
+/// envios
+fn mensagem(ctx: &Config, subject: String, corpo: String) -> Option<Message> {
+    let organizador = ctx.organizador_email.as_ref().unwrap();
+    let to = match organizador.parse() {
+        Ok(to) => to,
+        Err(e) => {
+            tracing::warn!("ORGANIZER_EMAIL `{organizador}` inválido: {e}");
+            return None;
+        }
+    };
+
+    let mut message = Message::builder()
+        .from(ctx.smtp_sender.parse().unwrap())
+        .to(to)
+        .subject(subject)
+        .body(corpo)
+        .unwrap();
+    if let Some(dkim) = &ctx.dkim {
+        message.sign(&dkim.config());
+    }
+
+    Some(message)
+}
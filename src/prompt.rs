@@ -0,0 +1,45 @@
+use std::io::Write;
+
+/// Pede ao usuário que digite `esperado` para confirmar uma ação sensível.
+///
+/// Retorna `false` para qualquer outra resposta
+pub fn confirmar(pergunta: &str, esperado: &str) -> bool {
+    eprint!("{pergunta} Digite `{esperado}` para confirmar: ");
+    std::io::stderr().flush().unwrap();
+
+    let mut resposta = String::new();
+    std::io::stdin().read_line(&mut resposta).unwrap();
+
+    resposta.trim() == esperado
+}
+
+/// Lê uma senha do terminal sem ecoá-la
+pub fn ler_senha(pergunta: &str) -> String {
+    rpassword::prompt_password(pergunta).unwrap()
+}
+
+/// Pede ao usuário que escolha uma das opções pelo número; Enter escolhe a
+/// primeira e `p` pula. Repete a pergunta para respostas inválidas
+pub fn escolher(pergunta: &str, opcoes: &[String]) -> Option<usize> {
+    eprintln!("{pergunta}");
+    for (i, o) in opcoes.iter().enumerate() {
+        eprintln!("  {}) {o}", i + 1);
+    }
+
+    loop {
+        eprint!("Número (Enter para o 1, `p` para pular): ");
+        std::io::stderr().flush().unwrap();
+
+        let mut resposta = String::new();
+        std::io::stdin().read_line(&mut resposta).unwrap();
+
+        match resposta.trim() {
+            "" => return Some(0),
+            "p" => return None,
+            n => match n.parse::<usize>() {
+                Ok(n) if (1..=opcoes.len()).contains(&n) => return Some(n - 1),
+                _ => eprintln!("Resposta inválida"),
+            },
+        }
+    }
+}